@@ -1,29 +1,59 @@
 extern crate interpreter;
 
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::fs;
 use std::io;
 
+use interpreter::{Backend, InterpreterBuilder};
 use parser::hello_world;
 
 fn interpreter(c: &mut Criterion) {
     c.bench_function("interpret hello world", |b| {
         b.iter(|| {
-            interpreter::Interpreter::new(io::sink()).interpret(&hello_world());
+            interpreter::Interpreter::new(io::empty(), io::sink())
+                .interpret(&hello_world())
+                .unwrap();
         })
     });
     c.bench_function("interpret file fizzbuzz", |b| {
         b.iter(|| {
-            interpreter::Interpreter::new(io::sink())
-                .interpret_file("../brainfuck-example/fizzbuzz.bf");
+            interpreter::Interpreter::new(io::empty(), io::sink())
+                .interpret_file("../brainfuck-example/fizzbuzz.bf")
+                .unwrap();
         })
     });
     c.bench_function("interpret file hello-world", |b| {
         b.iter(|| {
-            interpreter::Interpreter::new(io::sink())
-                .interpret_file("../brainfuck-example/hello-world.bf");
+            interpreter::Interpreter::new(io::empty(), io::sink())
+                .interpret_file("../brainfuck-example/hello-world.bf")
+                .unwrap();
         })
     });
 }
 
-criterion_group!(benches, interpreter);
+fn backends(c: &mut Criterion) {
+    let mut group = c.benchmark_group("backends");
+    for file in ["fizzbuzz", "hello-world"] {
+        let source = fs::read_to_string(format!("../brainfuck-example/{file}.bf")).unwrap();
+        for (name, backend) in [
+            ("tree walker", Backend::TreeWalker),
+            ("bytecode", Backend::Bytecode),
+            #[cfg(feature = "jit")]
+            ("jit", Backend::Jit),
+        ] {
+            group.bench_with_input(BenchmarkId::new(name, file), &source, |b, source| {
+                b.iter(|| {
+                    InterpreterBuilder::new()
+                        .backend(backend)
+                        .build(io::empty(), io::sink())
+                        .interpret_source(source)
+                        .unwrap();
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interpreter, backends);
 criterion_main!(benches);
@@ -1 +1,8 @@
-+++><[][++[++[]]]]
\ No newline at end of file
+uc: prints its input in upper case
+Expects lower case letters only and reads until the cell is zero at the end
+of input
+
+,[                      read a letter
+    >++++[<-------->-]< subtract 32
+    .,                  print it and read the next one
+]
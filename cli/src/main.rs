@@ -0,0 +1,924 @@
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::io::{self, stdin, stdout, BufRead, Read, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command as Process};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use clap::{ArgEnum, Args as ArgGroup, CommandFactory, ErrorKind, Parser as ArgParser, Subcommand};
+use error_messages::{Diagnostic, MessageFormat, Renderer, SourceMap, TAB_WIDTH};
+use interpreter::codegen::{self, Options};
+use interpreter::{
+    CellType, CellWidth, Debugger, EofBehavior, Interpreter, InterpreterBuilder, RuntimeError,
+    RuntimeResult, Status, Tape,
+};
+use langfuck::{Dialect, DialectError};
+use lexer::{lex, LexerToken, Span};
+use owo_colors::OwoColorize;
+use parser::lint::{self, LintKind, Lints};
+use parser::{canonical, format};
+use parser::{Instruction, Parser, ParserError, SpanTable};
+
+const EXIT_CODES: &str = "\
+Exit codes:
+    0  success
+    1  the program has errors or failed while running
+    2  invalid arguments
+    3  a file couldn't be read or written";
+
+/// Why `bf` failed, see [`EXIT_CODES`].
+#[derive(Debug, Clone, Copy)]
+enum Exit {
+    ProgramError = 1,
+    Usage = 2,
+    Io = 3,
+}
+
+fn exit(code: Exit) -> ! {
+    process::exit(code as i32)
+}
+
+#[derive(Debug, ArgParser)]
+#[clap(name = "bf", author, version, about = "Run, check and compile brainfuck programs", after_help = EXIT_CODES)]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run a program
+    Run {
+        #[clap(flatten)]
+        settings: Settings,
+        file: PathBuf,
+    },
+    /// Report the errors and lints in programs without running them
+    Check {
+        #[clap(flatten)]
+        settings: Settings,
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Compile a program for another target
+    Compile {
+        #[clap(flatten)]
+        settings: Settings,
+        #[clap(long, arg_enum, default_value = "c")]
+        target: Target,
+        /// Where to write the output, next to the input file by default
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// Build a native binary with the local `cc`, only with `--target c`
+        #[clap(long)]
+        cc: bool,
+        file: PathBuf,
+    },
+    /// Step through a program
+    Debug {
+        #[clap(flatten)]
+        settings: Settings,
+        file: PathBuf,
+    },
+    /// Translate a program into another dialect
+    Translate {
+        #[clap(flatten)]
+        settings: Settings,
+        /// Dialect to translate to
+        #[clap(long, value_name = "DIALECT", default_value = "brainfuck")]
+        to: Language,
+        /// Where to write the output, stdout by default
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        file: PathBuf,
+    },
+    /// Run commands line by line against one tape
+    Repl {
+        #[clap(flatten)]
+        settings: Settings,
+    },
+    /// Reformat programs in place
+    Fmt {
+        /// Number of columns lines are wrapped at
+        #[clap(long, default_value_t = 80)]
+        width: usize,
+        /// Number of spaces loop bodies are indented by
+        #[clap(long, default_value_t = 4)]
+        indent: usize,
+        /// Remove the text between commands
+        #[clap(long)]
+        strip_comments: bool,
+        /// Keep only the commands that do something when run by `bf`, on one
+        /// line. `<>` and `><` are kept, as they fail at the edges of the tape
+        #[clap(long, conflicts_with_all = &["width", "indent", "strip-comments"])]
+        minify: bool,
+        /// Don't write the files, fail if one of them isn't formatted
+        #[clap(long)]
+        check: bool,
+        /// How errors are reported
+        #[clap(long, arg_enum, default_value = "human")]
+        message_format: MessageFormatArg,
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Debug, ArgGroup)]
+struct Settings {
+    /// Number of cells on the tape
+    #[clap(
+        long,
+        default_value_t = interpreter::DEFAULT_TAPE_SIZE,
+        parse(try_from_str = parse_tape_size)
+    )]
+    tape_size: NonZeroUsize,
+    /// Number of bits in a cell
+    #[clap(long, default_value = "8", possible_values = ["8", "16", "32"])]
+    cell_width: u32,
+    /// What `,` does to the cell at the end of input
+    #[clap(long, arg_enum, default_value = "unchanged")]
+    eof: EofArg,
+    /// File the program reads its input from instead of stdin, no input when debugging
+    #[clap(long)]
+    input: Option<PathBuf>,
+    /// Language the program is written in: brainfuck, a built-in dialect or a
+    /// language file ending in `.json`
+    #[clap(
+        short = 'l',
+        long,
+        alias = "lang",
+        value_name = "DIALECT",
+        default_value = "brainfuck"
+    )]
+    dialect: Language,
+    /// How errors are reported
+    #[clap(long, arg_enum, default_value = "human")]
+    message_format: MessageFormatArg,
+    /// Don't warn about a lint
+    #[clap(
+        long,
+        value_name = "LINT",
+        possible_values = LintKind::ALL.map(LintKind::name)
+    )]
+    allow: Vec<LintKind>,
+}
+
+/// Plain brainfuck or a dialect of it.
+#[derive(Debug, Clone)]
+enum Language {
+    Brainfuck,
+    Dialect(Dialect),
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum MessageFormatArg {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum EofArg {
+    Unchanged,
+    Zero,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Target {
+    C,
+    Rust,
+    /// A static Linux x86-64 executable
+    Elf,
+    /// A WebAssembly module in the text format
+    Wat,
+    /// A WebAssembly module in the binary format
+    Wasm,
+}
+
+impl From<EofArg> for EofBehavior {
+    fn from(eof: EofArg) -> Self {
+        match eof {
+            EofArg::Unchanged => EofBehavior::Unchanged,
+            EofArg::Zero => EofBehavior::Zero,
+            EofArg::Max => EofBehavior::MaxValue,
+        }
+    }
+}
+
+impl From<MessageFormatArg> for MessageFormat {
+    fn from(format: MessageFormatArg) -> Self {
+        match format {
+            MessageFormatArg::Human => MessageFormat::Human,
+            MessageFormatArg::Json => MessageFormat::Json,
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = DialectError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "brainfuck" | "bf" => Ok(Language::Brainfuck),
+            _ => Dialect::load(name).map(Language::Dialect),
+        }
+    }
+}
+
+impl Language {
+    fn lex(&self, source: &str) -> Vec<LexerToken> {
+        match self {
+            Language::Brainfuck => lex(source),
+            Language::Dialect(dialect) => dialect.lex(source),
+        }
+    }
+
+    fn render(&self, instructions: &[Instruction]) -> String {
+        match self {
+            Language::Brainfuck => format::format(
+                &canonical::to_source(instructions),
+                &format::Options::default(),
+            )
+            .expect("generated source has matching brackets"),
+            Language::Dialect(dialect) => dialect.render(instructions),
+        }
+    }
+}
+
+impl Settings {
+    fn cell_width(&self) -> CellWidth {
+        match self.cell_width {
+            16 => CellWidth::Bits16,
+            32 => CellWidth::Bits32,
+            _ => CellWidth::Bits8,
+        }
+    }
+
+    fn builder(&self) -> InterpreterBuilder {
+        InterpreterBuilder::new()
+            .tape_size(self.tape_size)
+            .cell_width(self.cell_width())
+            .eof(self.eof.into())
+    }
+
+    /// Prints the diagnostics about `file` in the requested format.
+    fn report(&self, file: &Path, source: &str, diagnostics: &[Diagnostic]) {
+        let file = file.display().to_string();
+        Renderer::new(source)
+            .file(&file)
+            .format(self.message_format.into())
+            .emit(diagnostics);
+    }
+
+    /// The file from `--input`, or `default` without one.
+    fn input(&self, default: fn() -> Box<dyn Read>) -> Box<dyn Read> {
+        match &self.input {
+            Some(path) => match File::open(path) {
+                Ok(file) => Box::new(file),
+                Err(err) => {
+                    eprintln!("Error while reading {}: {}", path.display(), err);
+                    exit(Exit::Io)
+                }
+            },
+            None => default(),
+        }
+    }
+
+    fn lints(&self) -> Lints {
+        self.allow
+            .iter()
+            .fold(Lints::default(), |lints, &kind| lints.allow(kind))
+    }
+
+    fn options(&self) -> Options {
+        Options {
+            tape_size: self.tape_size,
+            cell_width: self.cell_width(),
+            eof: self.eof.into(),
+            ..Options::default()
+        }
+    }
+}
+
+fn main() {
+    let args: Args = Args::parse();
+    match args.command {
+        Command::Run { settings, file } => run(&settings, &file),
+        Command::Check { settings, files } => check(&settings, &files),
+        Command::Compile {
+            settings,
+            target,
+            output,
+            cc,
+            file,
+        } => {
+            if cc && !matches!(target, Target::C) {
+                Args::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "`--cc` can only be used with `--target c`",
+                    )
+                    .exit()
+            }
+            compile(&settings, target, output, cc, &file)
+        }
+        Command::Debug { settings, file } => debug(&settings, &file),
+        Command::Translate {
+            settings,
+            to,
+            output,
+            file,
+        } => translate(&settings, &to, output.as_deref(), &file),
+        Command::Repl { settings } => repl(&settings),
+        Command::Fmt {
+            width,
+            indent,
+            strip_comments,
+            minify,
+            check,
+            message_format,
+            files,
+        } => {
+            let options = format::Options {
+                width,
+                indent,
+                comments: !strip_comments,
+            };
+            fmt(&options, minify, check, message_format, &files)
+        }
+    }
+}
+
+fn run(settings: &Settings, file: &Path) {
+    let source = read_source(file);
+    let (instructions, spans) = parse(settings, file, &source);
+    let input = settings.input(|| Box::new(stdin()));
+    let mut interpreter = settings.builder().build(input, stdout());
+
+    if let Err(err) = interpreter.interpret_spanned(&instructions, &spans) {
+        settings.report(file, &source, &err.diagnostics());
+        exit(Exit::ProgramError)
+    }
+}
+
+fn check(settings: &Settings, files: &[PathBuf]) {
+    let mut failed = false;
+    for file in files {
+        let source = read_source(file);
+        let mut diagnostics = match Parser::new(settings.dialect.lex(&source)).parse_with_spans() {
+            Ok((instructions, spans)) => lint::lint(&instructions, &spans, settings.lints())
+                .iter()
+                .map(Diagnostic::from)
+                .collect(),
+            Err(errors) => {
+                failed = true;
+                RuntimeError::from(errors).diagnostics()
+            }
+        };
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span().map(|span| span.from));
+        if !diagnostics.is_empty() {
+            settings.report(file, &source, &diagnostics);
+        }
+    }
+    if failed {
+        exit(Exit::ProgramError)
+    }
+}
+
+fn translate(settings: &Settings, to: &Language, output: Option<&Path>, file: &Path) {
+    let source = read_source(file);
+    let (instructions, _) = parse(settings, file, &source);
+    let translated = to.render(&instructions);
+    match output {
+        Some(output) => {
+            if let Err(err) = fs::write(output, translated) {
+                eprintln!("Error while writing {}: {}", output.display(), err);
+                exit(Exit::Io)
+            }
+        }
+        None => print!("{translated}"),
+    }
+}
+
+fn compile(settings: &Settings, target: Target, output: Option<PathBuf>, cc: bool, file: &Path) {
+    let source = read_source(file);
+    let (instructions, _) = parse(settings, file, &source);
+    let options = settings.options();
+    let (code, extension) = match target {
+        Target::C => (
+            Ok(codegen::c::generate(&instructions, &options).into_bytes()),
+            "c",
+        ),
+        Target::Rust => (
+            Ok(codegen::rust::generate(&instructions, &options).into_bytes()),
+            "rs",
+        ),
+        Target::Elf => (codegen::elf::generate(&instructions, &options), ""),
+        Target::Wat => (
+            codegen::wasm::generate_wat(&instructions, &options).map(String::into_bytes),
+            "wat",
+        ),
+        Target::Wasm => (
+            codegen::wasm::generate_wasm(&instructions, &options),
+            "wasm",
+        ),
+    };
+    let code = code.unwrap_or_else(|err| {
+        eprintln!("Error while compiling {}: {}", file.display(), err);
+        exit(match err {
+            codegen::Error::MoveTooFar => Exit::ProgramError,
+            codegen::Error::TapeTooLarge => Exit::Usage,
+        })
+    });
+
+    let output = output.unwrap_or_else(|| match extension {
+        "" => executable(file),
+        extension => file.with_extension(extension),
+    });
+    let binary = executable(&output);
+    if is_same_file(&output, file) || (cc && is_same_file(&binary, file)) {
+        eprintln!(
+            "Error while compiling {}: the output would overwrite the program, choose another one with --output",
+            file.display()
+        );
+        exit(Exit::Usage)
+    }
+    if let Err(err) = fs::write(&output, code) {
+        eprintln!("Error while writing {}: {}", output.display(), err);
+        exit(Exit::Io)
+    }
+
+    #[cfg(unix)]
+    if matches!(target, Target::Elf) {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Err(err) = fs::set_permissions(&output, fs::Permissions::from_mode(0o755)) {
+            eprintln!(
+                "Error while making {} executable: {}",
+                output.display(),
+                err
+            );
+            exit(Exit::Io)
+        }
+    }
+
+    if cc {
+        let status = Process::new("cc")
+            .arg("-O2")
+            .arg("-o")
+            .arg(&binary)
+            .arg(&output)
+            .status();
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => process::exit(status.code().unwrap_or(1)),
+            Err(err) => {
+                eprintln!("Error while running cc: {}", err);
+                exit(Exit::Io)
+            }
+        }
+    }
+}
+
+/// Where an executable built from `path` goes: `path` without its extension, or
+/// with `.out` if it has none.
+fn executable(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(_) => path.with_extension(""),
+        None => path.with_extension("out"),
+    }
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn fmt(
+    options: &format::Options,
+    minify: bool,
+    check: bool,
+    message_format: MessageFormatArg,
+    files: &[PathBuf],
+) {
+    let mut failed = false;
+    let mut io_failed = false;
+    for file in files {
+        let source = read_source(file);
+        let formatted = if minify {
+            // Cells wrap, but moving off the tape is an error.
+            format::minify(
+                &source,
+                &canonical::Assumptions {
+                    wrapping_cells: true,
+                    unbounded_tape: false,
+                },
+            )
+        } else {
+            format::format(&source, options)
+        };
+        let formatted = match formatted {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                let diagnostics = errors.iter().map(Diagnostic::from).collect::<Vec<_>>();
+                Renderer::new(&source)
+                    .file(&file.display().to_string())
+                    .format(message_format.into())
+                    .emit(&diagnostics);
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", file.display());
+            failed = true;
+        } else if let Err(err) = fs::write(file, formatted) {
+            eprintln!("Error while writing {}: {}", file.display(), err);
+            io_failed = true;
+        }
+    }
+    if io_failed {
+        exit(Exit::Io)
+    }
+    if failed {
+        exit(Exit::ProgramError)
+    }
+}
+
+const DEBUG_HELP: &str = "\
+step, s [count]     execute the next command, or `count` commands
+next, n            execute the next command or the whole loop it starts
+out, o             run until the current loop is left
+continue, c        run until a breakpoint or the end of the program
+break, b [offset]  set a breakpoint at a byte offset, or list the breakpoints
+delete, d offset   remove the breakpoint at a byte offset
+tape, t [radius]   show the cells around the pointer
+quit, q            stop debugging";
+
+fn debug(settings: &Settings, file: &Path) {
+    let source = read_source(file);
+    // Stdin is where the debugger commands come from.
+    let input = settings.input(|| Box::new(io::empty()));
+    let interpreter = settings.builder().build(input, stdout());
+    let tokens = settings.dialect.lex(&source);
+    let mut debugger = match Debugger::with_tokens(interpreter, &source, tokens) {
+        Ok(debugger) => debugger,
+        Err(errors) => {
+            settings.report(file, &source, &RuntimeError::from(errors).diagnostics());
+            exit(Exit::ProgramError)
+        }
+    };
+
+    let map = SourceMap::new(&source);
+    println!("Type `help` for a list of commands.");
+    show_position(&debugger, &map);
+    let mut lines = stdin().lock().lines();
+    loop {
+        print!("(bf) ");
+        stdout().flush().ok();
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        let mut words = line.split_whitespace();
+        let command = words.next();
+        let argument = words.next().and_then(|word| word.parse::<usize>().ok());
+        let result = match command {
+            None => continue,
+            Some("step" | "s") => step(&mut debugger, argument.unwrap_or(1)),
+            Some("next" | "n") => debugger.step_over(),
+            Some("out" | "o") => debugger.step_out(),
+            Some("continue" | "c") => debugger.run(),
+            Some("break" | "b") => {
+                match argument {
+                    Some(offset) => match debugger.add_breakpoint(offset) {
+                        Some(span) => println!("Breakpoint at {}", location(&map, span)),
+                        None => println!("There is no command at or after {offset}"),
+                    },
+                    None => {
+                        for span in debugger.breakpoints() {
+                            println!("Breakpoint at {}", location(&map, span));
+                        }
+                    }
+                }
+                continue;
+            }
+            Some("delete" | "d") => {
+                match argument {
+                    Some(offset) if debugger.remove_breakpoint(offset) => {
+                        println!("Removed the breakpoint")
+                    }
+                    _ => println!("There is no breakpoint there"),
+                }
+                continue;
+            }
+            Some("tape" | "t") => {
+                show_tape(&debugger, argument.unwrap_or(4));
+                continue;
+            }
+            Some("quit" | "q") => break,
+            Some("help" | "h") => {
+                println!("{}", DEBUG_HELP);
+                continue;
+            }
+            Some(command) => {
+                println!("Unknown command `{command}`, type `help` for a list of commands.");
+                continue;
+            }
+        };
+
+        stdout().flush().ok();
+        match result {
+            Ok(Status::Finished) => {
+                println!("The program finished.");
+                show_tape(&debugger, 4);
+            }
+            Ok(Status::Breakpoint) => {
+                println!("Breakpoint");
+                show_position(&debugger, &map);
+            }
+            Ok(Status::Paused) => show_position(&debugger, &map),
+            Err(err) => settings.report(file, &source, &err.diagnostics()),
+        }
+    }
+}
+
+fn step<R: Read, W: Write>(debugger: &mut Debugger<R, W>, count: usize) -> RuntimeResult<Status> {
+    let mut status = Status::Paused;
+    for _ in 0..count {
+        status = debugger.step()?;
+        if status == Status::Finished {
+            break;
+        }
+    }
+    Ok(status)
+}
+
+/// The line and column of `span` for people, both starting at 1.
+fn location(map: &SourceMap, span: Span) -> String {
+    let location = map.location(span.from);
+    format!("{}:{}", location.line, location.column)
+}
+
+fn show_position<R: Read, W: Write>(debugger: &Debugger<R, W>, map: &SourceMap) {
+    if let Some(span) = debugger.position() {
+        let prefix = location(map, span);
+        let location = map.location(span.from);
+        let line = map
+            .line(location.line - 1)
+            .replace('\t', &" ".repeat(TAB_WIDTH));
+        println!("{} {}", prefix.blue(), line);
+        println!(
+            "{:width$} {}{}",
+            "",
+            " ".repeat(location.display_column - 1),
+            "^".bright_green(),
+            width = prefix.len()
+        );
+    }
+    show_tape(debugger, 4);
+}
+
+fn show_tape<R: Read, W: Write>(debugger: &Debugger<R, W>, radius: usize) {
+    show_cells(
+        debugger.tape(),
+        debugger.interpreter().cell_type(),
+        around_pointer(debugger.tape(), radius),
+    );
+}
+
+fn around_pointer(tape: &Tape, radius: usize) -> Range<usize> {
+    tape.pointer().saturating_sub(radius)..tape.pointer() + radius + 1
+}
+
+fn show_cells(tape: &Tape, cell_type: CellType, range: Range<usize>) {
+    let end = range.end.min(tape.cells().len());
+    let start = range.start.min(end);
+    let cells = (start..end)
+        .map(|i| {
+            let value = cell_type.value(tape.cells()[i]);
+            if i == tape.pointer() {
+                format!("[{}]", value).bold().to_string()
+            } else {
+                value.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    println!("pointer {}: {}", tape.pointer(), cells.join(" "));
+}
+
+const REPL_HELP: &str = "\
+:tape, :t [start..end]  show the cells in a range, or around the pointer
+:load, :l file          run a file against the tape
+:undo, :u               undo the last line or file
+:reset                  start over with an empty tape
+:quit, :q               leave the repl
+:help, :h               show this help";
+
+/// The name of the lines typed into the repl in diagnostics.
+const REPL_FILE: &str = "<repl>";
+
+/// Stdout that remembers whether the program stopped in the middle of a line.
+#[derive(Clone, Default)]
+struct ReplOutput {
+    mid_line: Rc<Cell<bool>>,
+}
+
+impl Write for ReplOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = stdout().write(buf)?;
+        if written > 0 {
+            self.mid_line.set(buf[written - 1] != b'\n');
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        stdout().flush()
+    }
+}
+
+struct Repl<'a> {
+    settings: &'a Settings,
+    interpreter: Interpreter<Box<dyn Read>, ReplOutput>,
+    output: ReplOutput,
+    /// The tape before every line or file that was run.
+    history: Vec<Tape>,
+}
+
+fn repl(settings: &Settings) {
+    let output = ReplOutput::default();
+    let mut repl = Repl {
+        settings,
+        interpreter: settings
+            .builder()
+            .build(settings.input(|| Box::new(stdin())), output.clone()),
+        output,
+        history: Vec::new(),
+    };
+    // The lines of a loop that isn't closed yet.
+    let mut pending = String::new();
+    println!("Type commands to run them, or `:help` for a list of meta-commands.");
+    loop {
+        print!("{}", if pending.is_empty() { "bf> " } else { "... " });
+        stdout().flush().ok();
+        let mut line = String::new();
+        // The lock on stdin is released before running, so `,` can read too.
+        match stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+
+        if pending.is_empty() {
+            if let Some(command) = line.trim().strip_prefix(':') {
+                if !repl.command(command) {
+                    break;
+                }
+                continue;
+            }
+        }
+        pending.push_str(&line);
+        match Parser::new(repl.settings.dialect.lex(&pending)).parse_with_spans() {
+            Err(errors)
+                if errors
+                    .iter()
+                    .all(|err| matches!(err, ParserError::UnexpectedEOF(_))) => {}
+            Err(errors) => {
+                let source = std::mem::take(&mut pending);
+                repl.settings.report(
+                    Path::new(REPL_FILE),
+                    &source,
+                    &RuntimeError::from(errors).diagnostics(),
+                );
+            }
+            Ok((instructions, spans)) => {
+                let source = std::mem::take(&mut pending);
+                repl.run(Path::new(REPL_FILE), &source, &instructions, &spans);
+            }
+        }
+    }
+}
+
+impl Repl<'_> {
+    /// Runs a meta-command. Returns false to leave the repl.
+    fn command(&mut self, command: &str) -> bool {
+        let (name, argument) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(name, argument)| (name, argument.trim()));
+        match name {
+            "tape" | "t" if argument.is_empty() => self.show_tape(),
+            "tape" | "t" => match parse_range(argument) {
+                Some(range) => {
+                    show_cells(self.interpreter.tape(), self.interpreter.cell_type(), range)
+                }
+                None => println!("Expected a range of cells like `0..20`"),
+            },
+            "load" | "l" if argument.is_empty() => println!("Expected a file to load"),
+            "load" | "l" => {
+                let file = Path::new(argument);
+                match fs::read_to_string(file) {
+                    Ok(source) => match Parser::new(self.settings.dialect.lex(&source))
+                        .parse_with_spans()
+                    {
+                        Ok((instructions, spans)) => self.run(file, &source, &instructions, &spans),
+                        Err(errors) => self.settings.report(
+                            file,
+                            &source,
+                            &RuntimeError::from(errors).diagnostics(),
+                        ),
+                    },
+                    Err(err) => println!("Error while reading {}: {}", file.display(), err),
+                }
+            }
+            "undo" | "u" => match self.history.pop() {
+                Some(tape) => {
+                    self.interpreter.set_tape(tape);
+                    self.show_tape();
+                }
+                None => println!("There is nothing to undo"),
+            },
+            "reset" => {
+                let input = self.settings.input(|| Box::new(stdin()));
+                self.interpreter = self.settings.builder().build(input, self.output.clone());
+                self.history.clear();
+                self.show_tape();
+            }
+            "quit" | "q" => return false,
+            "help" | "h" => println!("{}", REPL_HELP),
+            _ => println!("Unknown command `:{name}`, type `:help` for a list of commands."),
+        }
+        true
+    }
+
+    /// Runs the instructions, which are from `file`.
+    fn run(&mut self, file: &Path, source: &str, instructions: &[Instruction], spans: &SpanTable) {
+        self.history.push(self.interpreter.tape().clone());
+        let result = self.interpreter.interpret_spanned(instructions, spans);
+        stdout().flush().ok();
+        if self.output.mid_line.replace(false) {
+            println!();
+        }
+        if let Err(err) = result {
+            self.settings.report(file, source, &err.diagnostics());
+        }
+        self.show_tape();
+    }
+
+    fn show_tape(&self) {
+        let tape = self.interpreter.tape();
+        show_cells(tape, self.interpreter.cell_type(), around_pointer(tape, 4));
+    }
+}
+
+/// Parses `--tape-size`, the tape needs at least one cell.
+fn parse_tape_size(size: &str) -> Result<NonZeroUsize, String> {
+    match size.parse::<usize>() {
+        Ok(size) => {
+            NonZeroUsize::new(size).ok_or_else(|| "the tape needs at least one cell".to_string())
+        }
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Parses `start..end`.
+fn parse_range(range: &str) -> Option<Range<usize>> {
+    let (start, end) = range.split_once("..")?;
+    Some(start.trim().parse().ok()?..end.trim().parse().ok()?)
+}
+
+fn read_source(file: &Path) -> String {
+    match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Error while reading {}: {}", file.display(), err);
+            exit(Exit::Io)
+        }
+    }
+}
+
+/// Parses the program and warns about the lints found in it.
+fn parse(settings: &Settings, file: &Path, source: &str) -> (Vec<Instruction>, SpanTable) {
+    match Parser::new(settings.dialect.lex(source)).parse_with_spans() {
+        Ok((instructions, spans)) => {
+            let warnings = lint::lint(&instructions, &spans, settings.lints())
+                .iter()
+                .map(Diagnostic::from)
+                .collect::<Vec<_>>();
+            if !warnings.is_empty() {
+                settings.report(file, source, &warnings);
+            }
+            (instructions, spans)
+        }
+        Err(errors) => {
+            settings.report(file, source, &RuntimeError::from(errors).diagnostics());
+            exit(Exit::ProgramError)
+        }
+    }
+}
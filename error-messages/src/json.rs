@@ -0,0 +1,74 @@
+//! The shape of diagnostics in JSON.
+
+use lexer::Span;
+use serde::Serialize;
+
+use crate::{Renderer, Severity};
+
+#[derive(Serialize)]
+pub(crate) struct Diagnostic<'a> {
+    severity: Severity,
+    message: &'a str,
+    file: Option<&'a str>,
+    span: Option<ByteSpan>,
+    line: Option<usize>,
+    column: Option<usize>,
+    labels: Vec<Label<'a>>,
+    notes: &'a [String],
+    help: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Label<'a> {
+    span: ByteSpan,
+    line: usize,
+    column: usize,
+    message: &'a str,
+    primary: bool,
+}
+
+#[derive(Serialize)]
+struct ByteSpan {
+    from: usize,
+    to: usize,
+}
+
+impl From<Span> for ByteSpan {
+    fn from(span: Span) -> Self {
+        ByteSpan {
+            from: span.from,
+            to: span.to,
+        }
+    }
+}
+
+impl<'a> Diagnostic<'a> {
+    pub(crate) fn new(diagnostic: &'a crate::Diagnostic, renderer: &Renderer<'a>) -> Self {
+        let span = diagnostic.span();
+        let location = span.map(|span| renderer.map.location(span.from));
+        Diagnostic {
+            severity: diagnostic.severity,
+            message: &diagnostic.message,
+            file: renderer.file,
+            span: span.map(ByteSpan::from),
+            line: location.map(|location| location.line),
+            column: location.map(|location| location.column),
+            labels: diagnostic
+                .labels
+                .iter()
+                .map(|label| {
+                    let location = renderer.map.location(label.span.from);
+                    Label {
+                        span: label.span.into(),
+                        line: location.line,
+                        column: location.column,
+                        message: &label.message,
+                        primary: label.primary,
+                    }
+                })
+                .collect(),
+            notes: &diagnostic.notes,
+            help: diagnostic.help.as_deref(),
+        }
+    }
+}
@@ -1,70 +1,456 @@
-use lexer::Span;
-use owo_colors::OwoColorize;
+use std::io::{self, Write};
+
+use owo_colors::{OwoColorize, Style};
+use parser::lint::{Lint, LintKind};
 use parser::ParserError;
 
-pub fn print_error(err: &ParserError, input: &str) {
-    match err {
-        ParserError::UnexpectedEOF(span) => {
-            print_error_line("End of file before loop close");
-            print_location(span, input, "close the loop before the file ends")
-        }
-        ParserError::UnexpectedClosing(span) => {
-            print_error_line("Unexpected closing of loop");
-            print_location(span, input, "Remove the unnecessary \"]\"");
+pub use crate::diagnostic::{Diagnostic, Label, Severity};
+pub use crate::source_map::{Location, SourceMap, TAB_WIDTH};
+
+mod diagnostic;
+mod json;
+mod source_map;
+
+impl From<&ParserError> for Diagnostic {
+    fn from(err: &ParserError) -> Self {
+        match err {
+            ParserError::UnexpectedEOF(span) => Diagnostic::error("End of file before loop close")
+                .with_label(*span, "this loop is never closed")
+                .with_help("close the loop before the file ends"),
+            ParserError::UnexpectedClosing(span) => Diagnostic::error("Unexpected closing of loop")
+                .with_label(*span, "there is no loop to close")
+                .with_help("Remove the unnecessary \"]\""),
         }
     }
 }
 
-fn print_error_line(message: &str) {
-    eprintln!("{}: {}", "Error".bold().bright_red(), message);
+impl From<&Lint> for Diagnostic {
+    fn from(lint: &Lint) -> Self {
+        let diagnostic = match lint.kind {
+            LintKind::DeadLoop => {
+                let diagnostic = Diagnostic::warning("Loop never runs")
+                    .with_label(lint.span, "the current cell is always zero here");
+                match lint.cause {
+                    Some(cause) => diagnostic
+                        .with_secondary_label(cause, "a loop only ends when the cell is zero"),
+                    None => diagnostic.with_note("every cell starts at zero"),
+                }
+            }
+            LintKind::InfiniteLoop => Diagnostic::warning("Loop never ends")
+                .with_label(
+                    lint.span,
+                    "this loop changes neither the pointer nor a cell",
+                )
+                .with_help("add a `-` to the loop to count the cell down"),
+            LintKind::CancellingPair => Diagnostic::warning("Commands cancel each other out")
+                .with_label(lint.span, "these commands do nothing")
+                .with_help("remove them"),
+            LintKind::UnreachableCode => {
+                let diagnostic = Diagnostic::warning("Unreachable code")
+                    .with_label(lint.span, "this code is never run");
+                match lint.cause {
+                    Some(cause) => diagnostic
+                        .with_secondary_label(cause, "this loop is always entered and never ends"),
+                    None => diagnostic,
+                }
+            }
+            LintKind::NegativePointer => Diagnostic::warning("Pointer moves below the first cell")
+                .with_label(
+                    lint.span,
+                    "the pointer is always on the first cell before this",
+                ),
+        };
+        diagnostic.with_note(format!("this is the `{}` lint", lint.kind))
+    }
 }
 
-fn print_location(span: &Span, input: &str, note: &str) {
-    let line = get_line_of_error(span, input);
-    let info_string = format!("{} | ", line + 1);
-    let code_line = get_line(line, input);
-    eprintln!("{} {}", " ".repeat(info_string.len() - 3), "|".blue());
-    eprintln!("{}{}", info_string.blue(), code_line);
-    eprintln!(
-        "{} {}{}{}",
-        " ".repeat(info_string.len() - 3),
-        "|".blue(),
-        " ".repeat(
-            code_line
-                .split_at(get_span_of_line(span, input).from)
-                .0
-                .len()
-                + 1
-        ),
-        "^".repeat(span.to - span.from).bright_red(),
+/// Prints every error in one report.
+pub fn print_error(errors: &[ParserError], input: &str) {
+    emit(
+        &errors.iter().map(Diagnostic::from).collect::<Vec<_>>(),
+        input,
     );
-    eprintln!(
-        "{}{} {}: {}",
-        " ".repeat(info_string.len() - 2),
-        "=".blue(),
-        "note".bold(),
-        note
-    )
 }
 
-fn get_line(line: usize, input: &str) -> &str {
-    input.lines().nth(line).unwrap()
+/// Prints the diagnostics about `input` to stderr.
+pub fn emit(diagnostics: &[Diagnostic], input: &str) {
+    Renderer::new(input).emit(diagnostics);
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum MessageFormat {
+    /// Source lines with the labels underneath, for people.
+    #[default]
+    Human,
+    /// One JSON object per line, for tools. See [`Renderer::render`].
+    Json,
 }
 
-fn get_line_of_error(span: &Span, input: &str) -> usize {
-    input
-        .char_indices()
-        .filter(|(i, char)| *i <= span.from && *char == '\n')
-        .count()
-        + 1
+/// Renders diagnostics about a source.
+pub struct Renderer<'a> {
+    map: SourceMap<'a>,
+    file: Option<&'a str>,
+    color: bool,
+    format: MessageFormat,
 }
 
-fn get_span_of_line(span: &Span, input: &str) -> Span {
-    let line = get_line_of_error(span, input);
-    let lines_before = (0..line).map(|x| get_line(x, input)).collect::<String>();
+impl<'a> Renderer<'a> {
+    /// A renderer for diagnostics about `source`, with colors.
+    pub fn new(source: &'a str) -> Self {
+        Renderer {
+            map: SourceMap::new(source),
+            file: None,
+            color: true,
+            format: MessageFormat::Human,
+        }
+    }
+
+    /// The name of the file shown with the location of a diagnostic.
+    pub fn file(mut self, file: &'a str) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    /// Whether people get colored output. JSON is never colored.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn format(mut self, format: MessageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Prints the diagnostics to stderr.
+    pub fn emit(&self, diagnostics: &[Diagnostic]) {
+        self.render_all(diagnostics, &mut io::stderr().lock()).ok();
+    }
+
+    /// Renders the diagnostics one after another. People also get the number of
+    /// errors at the end if there is more than one.
+    pub fn render_all(&self, diagnostics: &[Diagnostic], out: &mut impl Write) -> io::Result<()> {
+        for (i, diagnostic) in diagnostics.iter().enumerate() {
+            if i > 0 && self.format == MessageFormat::Human {
+                writeln!(out)?;
+            }
+            self.render(diagnostic, out)?;
+        }
+        let errors = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count();
+        if errors > 1 && self.format == MessageFormat::Human {
+            writeln!(out)?;
+            self.render(&Diagnostic::error(format!("Found {errors} errors")), out)?;
+        }
+        Ok(())
+    }
+
+    /// Renders a diagnostic in the configured format.
+    ///
+    /// A JSON diagnostic is an object on a single line with the `severity`
+    /// (`"error"`, `"warning"` or `"note"`), the `message`, the `file` if there
+    /// is one, the byte `span` of the primary label as `{"from", "to"}` and its
+    /// `line` and `column`, the `labels` with their `span`, `line`, `column`,
+    /// `message` and whether they are `primary`, the `notes` and the `help`.
+    /// Lines and columns start at 1, like the ones people see.
+    pub fn render(&self, diagnostic: &Diagnostic, out: &mut impl Write) -> io::Result<()> {
+        match self.format {
+            MessageFormat::Human => self.render_human(diagnostic, out),
+            MessageFormat::Json => {
+                serde_json::to_writer(&mut *out, &json::Diagnostic::new(diagnostic, self))?;
+                writeln!(out)
+            }
+        }
+    }
+
+    fn render_human(&self, diagnostic: &Diagnostic, out: &mut impl Write) -> io::Result<()> {
+        let (title, style) = match diagnostic.severity {
+            Severity::Error => ("Error", Style::new().bold().bright_red()),
+            Severity::Warning => ("Warning", Style::new().bold().bright_yellow()),
+            Severity::Note => ("Note", Style::new().bold().bright_cyan()),
+        };
+        let blue = Style::new().blue();
+        writeln!(out, "{}: {}", self.paint(title, style), diagnostic.message)?;
+
+        let mut labels = diagnostic.labels.iter().collect::<Vec<_>>();
+        labels.sort_by_key(|label| label.span.from);
+        let gutter = labels
+            .iter()
+            .map(|label| (self.map.lines(label.span).1 + 1).to_string().len())
+            .max()
+            .unwrap_or(1);
+        let empty = " ".repeat(gutter);
+
+        if let Some(span) = diagnostic.span() {
+            let location = self.map.location(span.from);
+            let file = self.file.map(|file| format!("{file}:")).unwrap_or_default();
+            writeln!(
+                out,
+                "{}{} {file}{}:{}",
+                empty,
+                self.paint("-->", blue),
+                location.line,
+                location.column
+            )?;
+        }
+        if !labels.is_empty() {
+            writeln!(out, "{} {}", empty, self.paint("|", blue))?;
+        }
+        // Every line a label is shown on, in the order they are printed. Spans
+        // over more than four lines only show their first two and their last
+        // line.
+        let mut rows = Vec::new();
+        for label in labels {
+            let (first, last) = self.map.lines(label.span);
+            let lines = if last - first > 3 {
+                vec![first, first + 1, last]
+            } else {
+                (first..=last).collect()
+            };
+            rows.extend(lines.into_iter().map(|line| (line, label, line == last)));
+        }
+        // The sort is stable, so labels on the same line stay sorted.
+        rows.sort_by_key(|(line, _, _)| *line);
+
+        let mut printed = None;
+        for (line, label, is_last) in rows {
+            if printed != Some(line) {
+                if printed.is_some_and(|printed| line > printed + 1) {
+                    writeln!(out, "{}", self.paint("...", blue))?;
+                }
+                let number = format!("{:>gutter$} |", line + 1);
+                let text = self.map.line(line).replace('\t', &" ".repeat(TAB_WIDTH));
+                writeln!(out, "{} {}", self.paint(&number, blue), text)?;
+                printed = Some(line);
+            }
+
+            let (marker, marker_style) = if label.primary {
+                ("^", style)
+            } else {
+                ("-", blue)
+            };
+            let (start, end) = self.map.display_range(line, label.span);
+            let markers = marker.repeat(end.saturating_sub(start).max(1));
+            write!(
+                out,
+                "{} {} {}{}",
+                empty,
+                self.paint("|", blue),
+                " ".repeat(start),
+                self.paint(&markers, marker_style)
+            )?;
+            if is_last && !label.message.is_empty() {
+                write!(out, " {}", self.paint(&label.message, marker_style))?;
+            }
+            writeln!(out)?;
+        }
+
+        for note in &diagnostic.notes {
+            writeln!(
+                out,
+                "{} {} {}: {}",
+                empty,
+                self.paint("=", blue),
+                self.paint("note", Style::new().bold()),
+                note
+            )?;
+        }
+        if let Some(help) = &diagnostic.help {
+            writeln!(
+                out,
+                "{} {} {}: {}",
+                empty,
+                self.paint("=", blue),
+                self.paint("help", Style::new().bold()),
+                help
+            )?;
+        }
+        Ok(())
+    }
 
-    Span {
-        from: span.from - lines_before.len(),
-        to: span.to - lines_before.len(),
+    fn paint(&self, text: &str, style: Style) -> String {
+        if self.color {
+            text.style(style).to_string()
+        } else {
+            text.to_string()
+        }
     }
 }
+
+#[test]
+fn render_parser_errors() {
+    let source = "+>\n[-]<<]\n[";
+    let errors = parser::Parser::new(lexer::lex(source)).parse().unwrap_err();
+    let diagnostics = errors.iter().map(Diagnostic::from).collect::<Vec<_>>();
+    let mut out = Vec::new();
+    Renderer::new(source)
+        .file("test.bf")
+        .color(false)
+        .render_all(&diagnostics, &mut out)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "\
+Error: Unexpected closing of loop
+ --> test.bf:2:6
+  |
+2 | [-]<<]
+  |      ^ there is no loop to close
+  = help: Remove the unnecessary \"]\"
+
+Error: End of file before loop close
+ --> test.bf:3:1
+  |
+3 | [
+  | ^ this loop is never closed
+  = help: close the loop before the file ends
+
+Error: Found 2 errors
+"
+    );
+}
+
+#[test]
+fn render_labels() {
+    use lexer::Span;
+
+    let source = "größe\n\t[->\n+\n\n\n<]";
+    let diagnostic = Diagnostic::warning("Loop never ends")
+        .with_label(Span::from(9, 19), "this loop")
+        .with_secondary_label(Span::from(0, 7), "a comment")
+        .with_note("a note");
+    let mut out = Vec::new();
+    Renderer::new(source)
+        .color(false)
+        .render(&diagnostic, &mut out)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "\
+Warning: Loop never ends
+ --> 2:2
+  |
+1 | größe
+  | ----- a comment
+2 |     [->
+  |     ^^^
+3 | +
+  | ^
+...
+6 | <]
+  | ^^ this loop
+  = note: a note
+"
+    );
+}
+
+#[test]
+fn render_overlapping_labels() {
+    use lexer::Span;
+
+    let source = "[\n>\n-\n<\n+\n]";
+    let diagnostic = Diagnostic::warning("Loop never ends")
+        .with_label(Span::from(2, 3), "replaced")
+        .with_label(Span::from(0, 11), "this loop")
+        .with_secondary_label(Span::from(4, 5), "this")
+        .with_secondary_label(Span::from(2, 3), "and this");
+    assert_eq!(
+        diagnostic
+            .labels
+            .iter()
+            .filter(|label| label.primary)
+            .count(),
+        1
+    );
+    let mut out = Vec::new();
+    Renderer::new(source)
+        .color(false)
+        .render(&diagnostic, &mut out)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "\
+Warning: Loop never ends
+ --> 1:1
+  |
+1 | [
+  | ^
+2 | >
+  | ^
+  | - and this
+3 | -
+  | - this
+...
+6 | ]
+  | ^ this loop
+"
+    );
+}
+
+#[test]
+fn render_json() {
+    use lexer::Span;
+
+    let diagnostic = Diagnostic::error("Pointer moved below the first cell")
+        .with_label(Span::from(7, 9), "this moved the pointer off the tape")
+        .with_help("move right first");
+    let mut out = Vec::new();
+    Renderer::new("+\n字 [<<]")
+        .file("test.bf")
+        .format(MessageFormat::Json)
+        .render(&diagnostic, &mut out)
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "severity": "error",
+            "message": "Pointer moved below the first cell",
+            "file": "test.bf",
+            "span": {"from": 7, "to": 9},
+            "line": 2,
+            "column": 4,
+            "labels": [{
+                "span": {"from": 7, "to": 9},
+                "line": 2,
+                "column": 4,
+                "message": "this moved the pointer off the tape",
+                "primary": true
+            }],
+            "notes": [],
+            "help": "move right first"
+        })
+    );
+}
+
+#[test]
+fn render_lint() {
+    use lexer::{lex, Span};
+    use parser::lint::{lint, Lints};
+    use parser::Parser;
+
+    let source = "+[->+<]\n[-]";
+    let (instructions, spans) = Parser::new(lex(source)).parse_with_spans().unwrap();
+    let lints = lint(&instructions, &spans, Lints::default());
+    assert_eq!(lints[0].cause, Some(Span::from(6, 7)));
+    let mut out = Vec::new();
+    Renderer::new(source)
+        .color(false)
+        .render(&Diagnostic::from(&lints[0]), &mut out)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "\
+Warning: Loop never runs
+ --> 2:1
+  |
+1 | +[->+<]
+  |       - a loop only ends when the cell is zero
+2 | [-]
+  | ^^^ the current cell is always zero here
+  = note: this is the `dead-loop` lint
+"
+    );
+}
@@ -0,0 +1,169 @@
+use std::borrow::Cow;
+
+use lexer::Span;
+use unicode_width::UnicodeWidthChar;
+
+/// How many columns a tab takes up when rendered.
+pub const TAB_WIDTH: usize = 4;
+
+/// Where a byte offset is in the source, for people.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Location {
+    /// The line, starting at 1.
+    pub line: usize,
+    /// The character in the line, starting at 1.
+    pub column: usize,
+    /// The terminal column of the character in the line, starting at 1. Wide
+    /// characters take up two columns and tabs take up four.
+    pub display_column: usize,
+}
+
+/// Converts byte offsets in a source into lines and columns.
+///
+/// Lines end with `\n`, and a `\r` right before it doesn't belong to the line.
+/// The map either borrows or owns the source.
+pub struct SourceMap<'a> {
+    source: Cow<'a, str>,
+    /// The byte offset of the start of every line.
+    line_starts: Vec<usize>,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(source: impl Into<Cow<'a, str>>) -> Self {
+        let source = source.into();
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceMap {
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The number of lines. A source ending with a newline has an empty last
+    /// line.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The index of the line `offset` is on, starting at 0. Offsets past the
+    /// end are on the last line.
+    pub fn line_index(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    /// The text of the line with the given index, without its line ending.
+    pub fn line(&self, index: usize) -> &str {
+        let start = self.line_starts[index];
+        let end = self
+            .line_starts
+            .get(index + 1)
+            .map_or(self.source.len(), |next| next - 1);
+        let line = &self.source[start..end];
+        line.strip_suffix('\r').unwrap_or(line)
+    }
+
+    /// The byte offset of the start of the line with the given index.
+    pub fn line_start(&self, index: usize) -> usize {
+        self.line_starts[index]
+    }
+
+    pub fn location(&self, offset: usize) -> Location {
+        let offset = self.floor_char_boundary(offset);
+        let index = self.line_index(offset);
+        let before = &self.source[self.line_starts[index]..offset];
+        Location {
+            line: index + 1,
+            column: before.chars().count() + 1,
+            display_column: display_width(before) + 1,
+        }
+    }
+
+    /// The indices of the lines `span` covers. An empty span is on the line it
+    /// starts on.
+    pub fn lines(&self, span: Span) -> (usize, usize) {
+        let first = self.line_index(span.from);
+        let last = self.line_index(span.to.saturating_sub(1).max(span.from));
+        (first, last)
+    }
+
+    /// The terminal columns, starting at 0, where the part of `span` on the
+    /// line with the given index starts and ends.
+    pub(crate) fn display_range(&self, index: usize, span: Span) -> (usize, usize) {
+        let start = self.line_starts[index];
+        let text = self.line(index);
+        let column = |offset: usize| {
+            let mut end = offset.saturating_sub(start).min(text.len());
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            display_width(&text[..end])
+        };
+        (column(span.from), column(span.to))
+    }
+
+    fn floor_char_boundary(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+}
+
+/// The number of terminal columns `text` takes up.
+pub(crate) fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c {
+            '\t' => TAB_WIDTH,
+            c => c.width().unwrap_or(0),
+        })
+        .sum()
+}
+
+#[test]
+fn lines_and_columns() {
+    let map = SourceMap::new("+>\n[-]<<\n\n.");
+    assert_eq!(map.line_count(), 4);
+    assert_eq!(map.line(1), "[-]<<");
+    assert_eq!(map.line(2), "");
+    assert_eq!(
+        map.location(0),
+        Location {
+            line: 1,
+            column: 1,
+            display_column: 1
+        }
+    );
+    assert_eq!(map.location(6).line, 2);
+    assert_eq!(map.location(6).column, 4);
+    assert_eq!(map.location(2).column, 3);
+    assert_eq!(map.location(10).line, 4);
+    assert_eq!(map.location(100).line, 4);
+    assert_eq!(map.lines(Span::from(3, 8)), (1, 1));
+    assert_eq!(map.lines(Span::from(3, 9)), (1, 1));
+    assert_eq!(map.lines(Span::from(3, 11)), (1, 3));
+}
+
+#[test]
+fn unicode_and_line_endings() {
+    let map = SourceMap::new("größe +\r\n\tmain 字 [");
+    assert_eq!(map.line(0), "größe +");
+    assert_eq!(map.location(8).column, 7);
+    assert_eq!(map.location(8).display_column, 7);
+    let bracket = map.source().find('[').unwrap();
+    assert_eq!(
+        map.location(bracket),
+        Location {
+            line: 2,
+            column: 9,
+            display_column: 13
+        }
+    );
+    // Offsets inside a character belong to that character.
+    assert_eq!(map.location(3).column, 3);
+}
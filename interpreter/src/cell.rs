@@ -0,0 +1,140 @@
+/// How many bits a cell holds.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum CellWidth {
+    #[default]
+    Bits8,
+    Bits16,
+    Bits32,
+}
+
+/// What happens when `+` or `-` leaves the range of a cell.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum Overflow {
+    /// Wrap around to the other end of the range.
+    #[default]
+    Wrap,
+    /// Stay at the smallest or largest value.
+    Saturate,
+    /// Stop the program with a cell overflow or underflow error.
+    Error,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub(crate) enum CellError {
+    Overflow,
+    Underflow,
+}
+
+/// The width, signedness and overflow behaviour of the cells.
+///
+/// Cells are stored as the raw bits of their value, so `-1` in a signed 8-bit
+/// cell is stored as `0xff`. Output writes the lowest 8 bits of a cell and input
+/// stores the byte that was read, regardless of the cell type.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct CellType {
+    pub width: CellWidth,
+    pub signed: bool,
+    pub overflow: Overflow,
+}
+
+impl CellWidth {
+    pub fn bits(self) -> u32 {
+        match self {
+            Self::Bits8 => 8,
+            Self::Bits16 => 16,
+            Self::Bits32 => 32,
+        }
+    }
+}
+
+impl CellType {
+    pub fn new(width: CellWidth, signed: bool, overflow: Overflow) -> Self {
+        CellType {
+            width,
+            signed,
+            overflow,
+        }
+    }
+
+    /// The raw bits of a cell with every bit set.
+    pub fn mask(self) -> u32 {
+        u32::MAX >> (32 - self.width.bits())
+    }
+
+    pub fn min(self) -> i64 {
+        if self.signed {
+            -(1 << (self.width.bits() - 1))
+        } else {
+            0
+        }
+    }
+
+    pub fn max(self) -> i64 {
+        if self.signed {
+            (1 << (self.width.bits() - 1)) - 1
+        } else {
+            self.mask() as i64
+        }
+    }
+
+    /// Decodes the raw bits of a cell into its numeric value.
+    pub fn value(self, raw: u32) -> i64 {
+        let shift = 64 - self.width.bits();
+        if self.signed {
+            ((raw as i64) << shift) >> shift
+        } else {
+            raw as i64
+        }
+    }
+
+    /// Encodes a value into the raw bits of a cell, keeping the lowest bits.
+    pub fn raw(self, value: i64) -> u32 {
+        value as u32 & self.mask()
+    }
+
+    /// Adds `amount` to a cell according to the overflow behaviour.
+    pub(crate) fn add(self, raw: u32, amount: i64) -> Result<u32, CellError> {
+        let value = self.value(raw) + amount;
+        if value > self.max() {
+            match self.overflow {
+                Overflow::Wrap => Ok(self.raw(value)),
+                Overflow::Saturate => Ok(self.raw(self.max())),
+                Overflow::Error => Err(CellError::Overflow),
+            }
+        } else if value < self.min() {
+            match self.overflow {
+                Overflow::Wrap => Ok(self.raw(value)),
+                Overflow::Saturate => Ok(self.raw(self.min())),
+                Overflow::Error => Err(CellError::Underflow),
+            }
+        } else {
+            Ok(self.raw(value))
+        }
+    }
+}
+
+#[test]
+fn arithmetic() {
+    let byte = CellType::default();
+    assert_eq!(byte.add(255, 1), Ok(0));
+    assert_eq!(byte.add(0, -1), Ok(255));
+
+    let signed = CellType::new(CellWidth::Bits8, true, Overflow::Saturate);
+    assert_eq!(signed.add(signed.raw(127), 1), Ok(signed.raw(127)));
+    assert_eq!(signed.value(signed.add(0, -1).unwrap()), -1);
+    assert_eq!(
+        signed.value(signed.add(signed.raw(-128), -3).unwrap()),
+        -128
+    );
+
+    let word = CellType::new(CellWidth::Bits16, false, Overflow::Error);
+    assert_eq!(word.add(255, 1), Ok(256));
+    assert_eq!(word.add(0, -1), Err(CellError::Underflow));
+    assert_eq!(word.add(0xffff, 1), Err(CellError::Overflow));
+
+    let int = CellType::new(CellWidth::Bits32, true, Overflow::Wrap);
+    assert_eq!(
+        int.value(int.add(int.raw(i32::MAX as i64), 1).unwrap()),
+        i32::MIN as i64
+    );
+}
@@ -0,0 +1,108 @@
+use std::fmt::Write;
+
+use parser::ir::{self, Node, Op};
+use parser::Instruction;
+
+use crate::codegen::Options;
+use crate::{CellWidth, EofBehavior};
+
+/// Generates a standalone C program that reads from stdin and writes to stdout.
+pub fn generate(instructions: &[Instruction], options: &Options) -> String {
+    let cell = match options.cell_width {
+        CellWidth::Bits8 => "uint8_t",
+        CellWidth::Bits16 => "uint16_t",
+        CellWidth::Bits32 => "uint32_t",
+    };
+
+    let mut code = String::new();
+    code.push_str(
+        "#include <stddef.h>\n#include <stdint.h>\n#include <stdio.h>\n#include <stdlib.h>\n\n",
+    );
+    writeln!(code, "#define TAPE_SIZE {}", options.tape_size).unwrap();
+    writeln!(
+        code,
+        "\ntypedef {cell} cell;\nstatic cell tape[TAPE_SIZE];\n"
+    )
+    .unwrap();
+    code.push_str(
+        "static size_t move(size_t pointer, ptrdiff_t amount) {\n\
+         \x20   if (amount < 0 ? pointer < (size_t) -amount : TAPE_SIZE - pointer <= (size_t) amount) {\n\
+         \x20       fflush(stdout);\n\
+         \x20       fputs(\"pointer moved off the tape\\n\", stderr);\n\
+         \x20       exit(1);\n\
+         \x20   }\n\
+         \x20   return pointer + amount;\n\
+         }\n\n",
+    );
+    code.push_str("int main(void) {\n    size_t p = 0;\n");
+    block(
+        &mut code,
+        &ir::lower(instructions, options.passes),
+        options,
+        1,
+    );
+    code.push_str("    return 0;\n}\n");
+    code
+}
+
+fn block(code: &mut String, nodes: &[Node], options: &Options, depth: usize) {
+    // The blocks being written, innermost last, with their depth.
+    let mut blocks = vec![(nodes.iter(), depth)];
+    while let Some((block, depth)) = blocks.last_mut() {
+        let depth = *depth;
+        let Some(node) = block.next() else {
+            blocks.pop();
+            if !blocks.is_empty() {
+                writeln!(code, "{}}}", "    ".repeat(depth - 1)).unwrap();
+            }
+            continue;
+        };
+        let indent = "    ".repeat(depth);
+        match &node.op {
+            Op::Add(amount) => writeln!(code, "{indent}tape[p] += (cell) {amount};").unwrap(),
+            Op::Move(amount) => writeln!(code, "{indent}p = move(p, {amount});").unwrap(),
+            Op::Loop(body) => {
+                writeln!(code, "{indent}while (tape[p]) {{").unwrap();
+                blocks.push((body.iter(), depth + 1));
+            }
+            Op::Clear => writeln!(code, "{indent}tape[p] = 0;").unwrap(),
+            Op::Scan(amount) => {
+                writeln!(code, "{indent}while (tape[p]) p = move(p, {amount});").unwrap()
+            }
+            Op::Multiply(targets) => {
+                writeln!(code, "{indent}if (tape[p]) {{").unwrap();
+                for (offset, factor) in targets {
+                    writeln!(
+                        code,
+                        "{indent}    tape[move(p, {offset})] += (uint32_t) tape[p] * (uint32_t) {factor};"
+                    )
+                    .unwrap();
+                }
+                writeln!(code, "{indent}    tape[p] = 0;\n{indent}}}").unwrap();
+            }
+            Op::Output => writeln!(code, "{indent}putchar((unsigned char) tape[p]);").unwrap(),
+            Op::Input => {
+                let eof = match options.eof {
+                    EofBehavior::Unchanged => "tape[p]",
+                    EofBehavior::Zero => "0",
+                    EofBehavior::MaxValue => "(cell) -1",
+                };
+                writeln!(
+                    code,
+                    "{indent}{{\n{indent}    int c = getchar();\n{indent}    tape[p] = c == EOF ? {eof} : (cell) c;\n{indent}}}"
+                )
+                .unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn matches_interpreter() {
+    super::check_backend(
+        "program.c",
+        ("cc", &["-o", "program", "program.c"]),
+        crate::Backend::default(),
+        generate,
+    );
+}
@@ -0,0 +1,273 @@
+use lexer::Span;
+use parser::ir;
+use parser::Instruction;
+
+use crate::codegen::x86_64::{Assembler, Runtime, JAE, JB, JE, JS};
+use crate::codegen::{Error, Options};
+use crate::{CellType, EofBehavior, Overflow};
+
+/// Where the executable is loaded.
+const BASE: u64 = 0x40_0000;
+const PAGE: u64 = 0x1000;
+const HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+/// The output buffer sits right below the tape.
+const BUFFER_SIZE: i32 = 4096;
+const OFF_TAPE: &[u8] = b"pointer moved off the tape\n";
+
+const READ: u8 = 0;
+const WRITE: u8 = 1;
+const EXIT: u8 = 60;
+
+/// Generates a static Linux x86-64 executable that reads from stdin and writes
+/// to stdout using raw system calls.
+///
+/// Fails if a move, scan or multiply loop moves the pointer by more than fits
+/// into 32 bits, or if the tape doesn't fit into the address space.
+pub fn generate(instructions: &[Instruction], options: &Options) -> Result<Vec<u8>, Error> {
+    // Cells always take 4 bytes, and user space addresses have 47 bits.
+    let tape_bytes = (options.tape_size.get() as u64)
+        .checked_mul(4)
+        .filter(|&bytes| bytes < 1 << 46)
+        .ok_or(Error::TapeTooLarge)?;
+    let cell_type = CellType::new(options.cell_width, false, Overflow::Wrap);
+    let message = BASE + HEADER_SIZE + 2 * PROGRAM_HEADER_SIZE;
+    let entry = message + OFF_TAPE.len() as u64;
+
+    let mut assembler = Assembler::new(cell_type.mask());
+    let mut runtime = Syscalls {
+        tape_size: options.tape_size.get() as u64,
+        eof: match options.eof {
+            EofBehavior::Unchanged => None,
+            eof => Some(eof.apply(0, cell_type)),
+        },
+        flush_calls: Vec::new(),
+        off_tape: Vec::new(),
+        io_errors: Vec::new(),
+    };
+    // mov r12, tape; xor r13d, r13d; xor ebp, ebp
+    assembler.emit(&[0x49, 0xbc]);
+    let tape_address = assembler.position();
+    assembler.emit_u64(0);
+    assembler.emit(&[0x45, 0x31, 0xed, 0x31, 0xed]);
+    assembler
+        .block(&ir::lower(instructions, options.passes), &mut runtime)
+        .ok_or(Error::MoveTooFar)?;
+    runtime.finish(&mut assembler, message);
+
+    let end = entry + assembler.code.len() as u64;
+    let buffer = end.div_ceil(PAGE) * PAGE;
+    let tape = buffer + BUFFER_SIZE as u64;
+    assembler.code[tape_address..tape_address + 8].copy_from_slice(&tape.to_le_bytes());
+
+    let mut elf = Vec::new();
+    // e_ident: ELF64, little endian, version 1, System V
+    elf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    elf.extend_from_slice(&[0; 8]);
+    // e_type: executable, e_machine: x86-64, e_version
+    elf.extend_from_slice(&2u16.to_le_bytes());
+    elf.extend_from_slice(&0x3eu16.to_le_bytes());
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    // e_entry, e_phoff, e_shoff, e_flags
+    elf.extend_from_slice(&entry.to_le_bytes());
+    elf.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+    elf.extend_from_slice(&0u64.to_le_bytes());
+    elf.extend_from_slice(&0u32.to_le_bytes());
+    // e_ehsize, e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx
+    for value in [HEADER_SIZE, PROGRAM_HEADER_SIZE, 2, 64, 0, 0] {
+        elf.extend_from_slice(&(value as u16).to_le_bytes());
+    }
+    // The file itself is mapped readable and executable, the output buffer and
+    // the tape are zeroed memory after it.
+    program_header(&mut elf, 0b101, BASE, end - BASE, end - BASE);
+    program_header(&mut elf, 0b110, buffer, 0, BUFFER_SIZE as u64 + tape_bytes);
+    elf.extend_from_slice(OFF_TAPE);
+    elf.extend_from_slice(&assembler.code);
+    Ok(elf)
+}
+
+fn program_header(elf: &mut Vec<u8>, flags: u32, address: u64, file_size: u64, memory_size: u64) {
+    // p_type: loadable, p_flags
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    elf.extend_from_slice(&flags.to_le_bytes());
+    // p_offset, p_vaddr, p_paddr, p_filesz, p_memsz, p_align
+    for value in [0, address, address, file_size, memory_size, PAGE] {
+        elf.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+// Besides the registers used by the assembler, rbp holds the number of bytes in
+// the output buffer.
+
+/// Checks the edges inline and does I/O with system calls.
+struct Syscalls {
+    tape_size: u64,
+    /// The value of a cell after reading past the end of input.
+    eof: Option<u32>,
+    /// Jumps to patch with the address of the code that stops the program.
+    flush_calls: Vec<usize>,
+    off_tape: Vec<usize>,
+    io_errors: Vec<usize>,
+}
+
+impl Syscalls {
+    fn syscall(assembler: &mut Assembler, number: u8, fd: u8) {
+        // mov eax, number; mov edi, fd; syscall
+        assembler.emit(&[0xb8, number, 0, 0, 0, 0xbf, fd, 0, 0, 0, 0x0f, 0x05]);
+    }
+
+    /// Stops the program with exit code 1 if the last system call failed.
+    fn check(&mut self, assembler: &mut Assembler) {
+        // test rax, rax; js io_error
+        assembler.emit(&[0x48, 0x85, 0xc0]);
+        let error = assembler.jump_if(JS);
+        self.io_errors.push(error);
+    }
+
+    fn flush(&mut self, assembler: &mut Assembler) {
+        // call flush
+        assembler.emit(&[0xe8]);
+        assembler.emit_u32(0);
+        self.flush_calls.push(assembler.position() - 4);
+    }
+
+    fn exit(assembler: &mut Assembler, code: u8) {
+        Self::syscall(assembler, EXIT, code);
+    }
+
+    /// Emits the end of the program and the code the jumps lead to.
+    fn finish(mut self, assembler: &mut Assembler, message: u64) {
+        self.flush(assembler);
+        Self::exit(assembler, 0);
+
+        let off_tape = assembler.position();
+        self.flush(assembler);
+        // mov esi, message; mov edx, len
+        assembler.emit(&[0xbe]);
+        assembler.emit_u32(message as u32);
+        assembler.emit(&[0xba]);
+        assembler.emit_u32(OFF_TAPE.len() as u32);
+        Self::syscall(assembler, WRITE, 2);
+        let io_error = assembler.position();
+        Self::exit(assembler, 1);
+
+        // Writes the whole output buffer: lea rsi, [r12 - BUFFER_SIZE]
+        let flush = assembler.position();
+        assembler.emit(&[0x49, 0x8d, 0xb4, 0x24]);
+        assembler.emit(&(-BUFFER_SIZE).to_le_bytes());
+        let again = assembler.position();
+        // test rbp, rbp; je done; mov rdx, rbp
+        assembler.emit(&[0x48, 0x85, 0xed]);
+        let done = assembler.jump_if(JE);
+        assembler.emit(&[0x48, 0x89, 0xea]);
+        Self::syscall(assembler, WRITE, 1);
+        self.check(assembler);
+        // add rsi, rax; sub rbp, rax; jmp again
+        assembler.emit(&[0x48, 0x01, 0xc6, 0x48, 0x29, 0xc5]);
+        let back = assembler.jump();
+        assembler.patch(back, again);
+        assembler.patch_here(done);
+        // ret
+        assembler.emit(&[0xc3]);
+
+        for at in self.off_tape {
+            assembler.patch(at, off_tape);
+        }
+        for at in self.io_errors {
+            assembler.patch(at, io_error);
+        }
+        for at in self.flush_calls {
+            assembler.patch(at, flush);
+        }
+    }
+}
+
+impl Runtime for Syscalls {
+    fn move_by(&mut self, assembler: &mut Assembler, amount: i32, _: Span) {
+        // lea rax, [r13 + amount]; mov rcx, tape_size; cmp rax, rcx; jae off_tape
+        assembler.next_pointer(amount);
+        assembler.emit(&[0x48, 0xb9]);
+        assembler.emit_u64(self.tape_size);
+        assembler.emit(&[0x48, 0x39, 0xc8]);
+        let off_tape = assembler.jump_if(JAE);
+        self.off_tape.push(off_tape);
+        assembler.set_pointer();
+    }
+
+    fn input(&mut self, assembler: &mut Assembler, _: Span) {
+        self.flush(assembler);
+        // lea rsi, [cell]; mov edx, 1
+        assembler.cell_address();
+        assembler.emit(&[0xba, 1, 0, 0, 0]);
+        Self::syscall(assembler, READ, 0);
+        self.check(assembler);
+        let eof = assembler.jump_if(JE);
+        // The byte was read into the lowest byte of the cell:
+        // movzx eax, byte [rsi]
+        assembler.emit(&[0x0f, 0xb6, 0x06]);
+        assembler.store_eax();
+        let done = assembler.jump();
+        assembler.patch_here(eof);
+        if let Some(value) = self.eof {
+            assembler.store(value);
+        }
+        assembler.patch_here(done);
+    }
+
+    fn output(&mut self, assembler: &mut Assembler, _: Span) {
+        // mov eax, dword [cell]; mov byte [r12 + rbp - BUFFER_SIZE], al; inc rbp
+        assembler.emit(&[0x43, 0x8b, 0x04, 0xac]);
+        assembler.emit(&[0x41, 0x88, 0x84, 0x2c]);
+        assembler.emit(&(-BUFFER_SIZE).to_le_bytes());
+        assembler.emit(&[0x48, 0xff, 0xc5]);
+        // cmp rbp, BUFFER_SIZE; jb done; call flush
+        assembler.emit(&[0x48, 0x81, 0xfd]);
+        assembler.emit(&BUFFER_SIZE.to_le_bytes());
+        let done = assembler.jump_if(JB);
+        self.flush(assembler);
+        assembler.patch_here(done);
+    }
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn matches_interpreter() {
+    let depth = 100_000;
+    let deep = format!("+{}{}.", "[-".repeat(depth), "]".repeat(depth));
+    let programs = [
+        (include_str!("../../../brainfuck-example/fizzbuzz.bf"), ""),
+        (",[>++++[<-------->-]<.,]", "hello"),
+        ("+++[>+++++<-]>[<+++++>-]<.>>+[<]", ""),
+        (&deep, ""),
+    ];
+    for (source, input) in programs {
+        for cell_width in [crate::CellWidth::Bits8, crate::CellWidth::Bits16] {
+            let options = Options {
+                cell_width,
+                eof: EofBehavior::Zero,
+                ..Options::default()
+            };
+            let instructions = parser::Parser::new(lexer::lex(source)).parse().unwrap();
+            super::check_generated(
+                "program",
+                &generate(&instructions, &options).unwrap(),
+                None,
+                &instructions,
+                crate::InterpreterBuilder::new()
+                    .tape_size(options.tape_size)
+                    .cell_width(cell_width)
+                    .eof(options.eof),
+                input,
+            );
+        }
+    }
+}
+
+#[test]
+fn tape_too_large() {
+    let options = Options {
+        tape_size: std::num::NonZeroUsize::MAX,
+        ..Options::default()
+    };
+    assert_eq!(generate(&[], &options), Err(Error::TapeTooLarge));
+}
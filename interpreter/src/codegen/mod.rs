@@ -0,0 +1,200 @@
+//! Translates programs into source code or binaries for other targets.
+//!
+//! The generated programs behave like an [`Interpreter`](crate::Interpreter)
+//! built with the same [`Options`]: cells wrap around on overflow and moving
+//! past either edge of the tape stops the program with exit code 1, or with a
+//! trap in WebAssembly.
+
+use std::fmt;
+use std::num::NonZeroUsize;
+
+use parser::ir::Passes;
+
+use crate::{CellWidth, EofBehavior, DEFAULT_TAPE_SIZE};
+
+pub mod c;
+pub mod elf;
+pub mod rust;
+pub mod wasm;
+
+pub(crate) mod x86_64;
+
+/// The settings of the generated program.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Options {
+    pub tape_size: NonZeroUsize,
+    pub cell_width: CellWidth,
+    pub eof: EofBehavior,
+    pub passes: Passes,
+}
+
+/// Why a program can't be compiled for a target.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Error {
+    /// The program moves the pointer further at once than the target can encode.
+    MoveTooFar,
+    /// The tape doesn't fit into the target's memory.
+    TapeTooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MoveTooFar => write!(f, "the program moves the pointer too far at once"),
+            Error::TapeTooLarge => write!(f, "the tape doesn't fit into the target's memory"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            tape_size: DEFAULT_TAPE_SIZE,
+            cell_width: CellWidth::default(),
+            eof: EofBehavior::default(),
+            passes: Passes::default(),
+        }
+    }
+}
+
+/// A directory for the files of a test, removed when the test ends, also when
+/// it fails.
+#[cfg(test)]
+struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+/// Checks that a generated program prints what `interpreter` prints for the
+/// same input, and that it exits with 1 where the interpreter fails.
+///
+/// `code` is written to `file` and turned into an executable `program` with
+/// `compiler`, which is skipped with a message if it isn't installed. Without
+/// a compiler, `file` is the executable.
+#[cfg(test)]
+pub(crate) fn check_generated(
+    file: &str,
+    code: &[u8],
+    compiler: Option<(&str, &[&str])>,
+    instructions: &[parser::Instruction],
+    interpreter: crate::InterpreterBuilder,
+    input: &str,
+) {
+    use std::io::{ErrorKind, Write};
+    use std::process::{Command, Stdio};
+
+    let dir = TestDir(std::env::temp_dir().join(format!("bf-{}-{}", file, std::process::id())));
+    std::fs::create_dir_all(&dir.0).unwrap();
+    std::fs::write(dir.0.join(file), code).unwrap();
+
+    let executable = match compiler {
+        Some((compiler, args)) => {
+            match Command::new(compiler)
+                .current_dir(&dir.0)
+                .args(args)
+                .status()
+            {
+                Ok(status) => assert!(status.success(), "{compiler} failed on {file}"),
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    eprintln!("skipped: `{compiler}` is not installed to build {file}");
+                    return;
+                }
+                Err(err) => panic!("couldn't run {compiler}: {err}"),
+            }
+            dir.0.join("program")
+        }
+        None => {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                let permissions = std::fs::Permissions::from_mode(0o755);
+                std::fs::set_permissions(dir.0.join(file), permissions).unwrap();
+            }
+            dir.0.join(file)
+        }
+    };
+
+    let mut expected = Vec::new();
+    let result = interpreter
+        .build(input.as_bytes(), &mut expected)
+        .interpret(instructions);
+    let mut child = Command::new(executable)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.stdout, expected);
+    assert_eq!(
+        output.status.code(),
+        Some(if result.is_ok() { 0 } else { 1 })
+    );
+}
+
+/// Checks the programs `generate` makes against the interpreter with every cell
+/// width and end of input behaviour, with a small tape and when the pointer
+/// moves off the tape. See [`check_generated`] for `file` and `compiler`.
+#[cfg(test)]
+pub(crate) fn check_backend(
+    file: &str,
+    compiler: (&str, &[&str]),
+    backend: crate::Backend,
+    generate: impl Fn(&[parser::Instruction], &Options) -> String,
+) {
+    let mut cases = vec![(
+        include_str!("../../../brainfuck-example/fizzbuzz.bf"),
+        "",
+        Options::default(),
+    )];
+    for cell_width in [CellWidth::Bits8, CellWidth::Bits16, CellWidth::Bits32] {
+        for eof in [
+            EofBehavior::Unchanged,
+            EofBehavior::Zero,
+            EofBehavior::MaxValue,
+        ] {
+            let options = Options {
+                cell_width,
+                eof,
+                ..Options::default()
+            };
+            cases.push((",>,>,<<.>.>.-.", "a", options));
+        }
+    }
+    let small = Options {
+        tape_size: NonZeroUsize::new(4).unwrap(),
+        ..Options::default()
+    };
+    cases.push((">>>+++[-<+>]<.", "", small));
+    cases.push(("+.[>+.]", "", small));
+    cases.push(("+<", "", Options::default()));
+
+    for (source, input, options) in cases {
+        let instructions = parser::Parser::new(lexer::lex(source)).parse().unwrap();
+        check_generated(
+            file,
+            generate(&instructions, &options).as_bytes(),
+            Some(compiler),
+            &instructions,
+            crate::InterpreterBuilder::new()
+                .tape_size(options.tape_size)
+                .cell_width(options.cell_width)
+                .eof(options.eof)
+                .backend(backend),
+            input,
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt::Write;
+
+use parser::ir::{self, Node, Op};
+use parser::Instruction;
+
+use crate::codegen::Options;
+use crate::{CellWidth, EofBehavior};
+
+/// Generates a standalone `main.rs` that reads from stdin and writes to stdout.
+pub fn generate(instructions: &[Instruction], options: &Options) -> String {
+    let cell = match options.cell_width {
+        CellWidth::Bits8 => "u8",
+        CellWidth::Bits16 => "u16",
+        CellWidth::Bits32 => "u32",
+    };
+
+    let mut code = String::new();
+    code.push_str("#![allow(dead_code, unused_assignments, unused_mut, unused_variables)]\n\n");
+    code.push_str("use std::io::{Read, Write};\n\n");
+    writeln!(code, "const TAPE_SIZE: usize = {};", options.tape_size).unwrap();
+    writeln!(code, "type Cell = {cell};\n").unwrap();
+    code.push_str(
+        "fn step(pointer: usize, amount: isize, output: &mut impl Write) -> usize {\n\
+         \x20   match pointer.checked_add_signed(amount) {\n\
+         \x20       Some(pointer) if pointer < TAPE_SIZE => pointer,\n\
+         \x20       _ => {\n\
+         \x20           let _ = output.flush();\n\
+         \x20           eprintln!(\"pointer moved off the tape\");\n\
+         \x20           std::process::exit(1)\n\
+         \x20       }\n\
+         \x20   }\n\
+         }\n\n",
+    );
+    code.push_str(
+        "fn main() {\n\
+         \x20   let mut tape: Vec<Cell> = vec![0; TAPE_SIZE];\n\
+         \x20   let mut p = 0;\n\
+         \x20   let mut input = std::io::stdin().lock().bytes();\n\
+         \x20   let mut output = std::io::stdout().lock();\n",
+    );
+    block(
+        &mut code,
+        &ir::lower(instructions, options.passes),
+        options,
+        1,
+    );
+    code.push_str("    output.flush().unwrap();\n}\n");
+    code
+}
+
+fn block(code: &mut String, nodes: &[Node], options: &Options, depth: usize) {
+    // The blocks being written, innermost last, with their depth.
+    let mut blocks = vec![(nodes.iter(), depth)];
+    while let Some((block, depth)) = blocks.last_mut() {
+        let depth = *depth;
+        let Some(node) = block.next() else {
+            blocks.pop();
+            if !blocks.is_empty() {
+                writeln!(code, "{}}}", "    ".repeat(depth - 1)).unwrap();
+            }
+            continue;
+        };
+        let indent = "    ".repeat(depth);
+        match &node.op {
+            Op::Add(amount) => writeln!(
+                code,
+                "{indent}tape[p] = tape[p].wrapping_add({amount}i64 as Cell);"
+            )
+            .unwrap(),
+            Op::Move(amount) => {
+                writeln!(code, "{indent}p = step(p, {amount}, &mut output);").unwrap()
+            }
+            Op::Loop(body) => {
+                writeln!(code, "{indent}while tape[p] != 0 {{").unwrap();
+                blocks.push((body.iter(), depth + 1));
+            }
+            Op::Clear => writeln!(code, "{indent}tape[p] = 0;").unwrap(),
+            Op::Scan(amount) => writeln!(
+                code,
+                "{indent}while tape[p] != 0 {{\n{indent}    p = step(p, {amount}, &mut output);\n{indent}}}"
+            )
+            .unwrap(),
+            Op::Multiply(targets) => {
+                writeln!(code, "{indent}if tape[p] != 0 {{").unwrap();
+                for (offset, factor) in targets {
+                    writeln!(
+                        code,
+                        "{indent}    let target = step(p, {offset}, &mut output);\n\
+                         {indent}    tape[target] = tape[target].wrapping_add(tape[p].wrapping_mul({factor}i64 as Cell));"
+                    )
+                    .unwrap();
+                }
+                writeln!(code, "{indent}    tape[p] = 0;\n{indent}}}").unwrap();
+            }
+            Op::Output => writeln!(
+                code,
+                "{indent}output.write_all(&[tape[p] as u8]).unwrap();"
+            )
+            .unwrap(),
+            Op::Input => {
+                let eof = match options.eof {
+                    EofBehavior::Unchanged => "{}",
+                    EofBehavior::Zero => "tape[p] = 0",
+                    EofBehavior::MaxValue => "tape[p] = Cell::MAX",
+                };
+                writeln!(
+                    code,
+                    "{indent}output.flush().unwrap();\n\
+                     {indent}match input.next() {{\n\
+                     {indent}    Some(byte) => tape[p] = byte.unwrap() as Cell,\n\
+                     {indent}    None => {eof},\n\
+                     {indent}}}"
+                )
+                .unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn matches_tree_walker() {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    super::check_backend(
+        "main.rs",
+        (&rustc, &["--edition", "2021", "-o", "program", "main.rs"]),
+        crate::Backend::TreeWalker,
+        generate,
+    );
+}
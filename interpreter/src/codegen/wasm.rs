@@ -0,0 +1,435 @@
+use std::fmt::Write;
+
+use parser::ir::{self, Node, Op};
+use parser::Instruction;
+
+use crate::codegen::{Error, Options};
+use crate::{CellType, CellWidth, EofBehavior, Overflow};
+
+const PAGE_SIZE: usize = 0x1_0000;
+
+// The function has three locals: the address of the current cell, the value of
+// a multiply loop and the byte that was read.
+const POINTER: u32 = 0;
+const VALUE: u32 = 1;
+const BYTE: u32 = 2;
+
+const READ_BYTE: u32 = 0;
+const WRITE_BYTE: u32 = 1;
+
+/// The WebAssembly instructions the generated code uses.
+#[derive(Debug, Clone, Copy)]
+enum Wasm {
+    Block,
+    Loop,
+    If,
+    IfResult,
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Unreachable,
+    Call(u32),
+    LocalGet(u32),
+    LocalTee(u32),
+    Load,
+    Store,
+    Const(i32),
+    Eqz,
+    LtS,
+    GeU,
+    Add,
+    Mul,
+    And,
+}
+
+/// Generates a module in the WebAssembly text format.
+///
+/// The module imports `read_byte` (returning a byte or -1 at the end of input)
+/// and `write_byte` from `env`, and exports the tape as `memory` and the
+/// program as `run`. Moving off the tape traps. Fails if the tape doesn't fit
+/// into 2 GiB of memory.
+pub fn generate_wat(instructions: &[Instruction], options: &Options) -> Result<String, Error> {
+    let (load, store) = match options.cell_width {
+        CellWidth::Bits8 => ("i32.load8_u", "i32.store8"),
+        CellWidth::Bits16 => ("i32.load16_u", "i32.store16"),
+        CellWidth::Bits32 => ("i32.load", "i32.store"),
+    };
+
+    let body = body(instructions, options)?;
+    let mut code = String::new();
+    code.push_str("(module\n");
+    code.push_str("  (import \"env\" \"read_byte\" (func $read_byte (result i32)))\n");
+    code.push_str("  (import \"env\" \"write_byte\" (func $write_byte (param i32)))\n");
+    writeln!(code, "  (memory (export \"memory\") {})", pages(options)).unwrap();
+    code.push_str("  (func (export \"run\") (local i32 i32 i32)\n");
+    let mut depth = 2;
+    for instruction in body {
+        if matches!(instruction, Wasm::Else | Wasm::End) {
+            depth -= 1;
+        }
+        code.push_str(&"  ".repeat(depth));
+        match instruction {
+            Wasm::Block => code.push_str("block"),
+            Wasm::Loop => code.push_str("loop"),
+            Wasm::If => code.push_str("if"),
+            Wasm::IfResult => code.push_str("if (result i32)"),
+            Wasm::Else => code.push_str("else"),
+            Wasm::End => code.push_str("end"),
+            Wasm::Br(depth) => write!(code, "br {depth}").unwrap(),
+            Wasm::BrIf(depth) => write!(code, "br_if {depth}").unwrap(),
+            Wasm::Unreachable => code.push_str("unreachable"),
+            Wasm::Call(READ_BYTE) => code.push_str("call $read_byte"),
+            Wasm::Call(_) => code.push_str("call $write_byte"),
+            Wasm::LocalGet(local) => write!(code, "local.get {local}").unwrap(),
+            Wasm::LocalTee(local) => write!(code, "local.tee {local}").unwrap(),
+            Wasm::Load => code.push_str(load),
+            Wasm::Store => code.push_str(store),
+            Wasm::Const(value) => write!(code, "i32.const {value}").unwrap(),
+            Wasm::Eqz => code.push_str("i32.eqz"),
+            Wasm::LtS => code.push_str("i32.lt_s"),
+            Wasm::GeU => code.push_str("i32.ge_u"),
+            Wasm::Add => code.push_str("i32.add"),
+            Wasm::Mul => code.push_str("i32.mul"),
+            Wasm::And => code.push_str("i32.and"),
+        }
+        code.push('\n');
+        if matches!(
+            instruction,
+            Wasm::Block | Wasm::Loop | Wasm::If | Wasm::IfResult | Wasm::Else
+        ) {
+            depth += 1;
+        }
+    }
+    code.push_str("  )\n)\n");
+    Ok(code)
+}
+
+/// Generates the same module as [`generate_wat`] in the binary format.
+pub fn generate_wasm(instructions: &[Instruction], options: &Options) -> Result<Vec<u8>, Error> {
+    let (load, store, align) = match options.cell_width {
+        CellWidth::Bits8 => (0x2d, 0x3a, 0),
+        CellWidth::Bits16 => (0x2f, 0x3b, 1),
+        CellWidth::Bits32 => (0x28, 0x36, 2),
+    };
+
+    let mut code = vec![1, 3, 0x7f];
+    for instruction in body(instructions, options)? {
+        match instruction {
+            Wasm::Block => code.extend([0x02, 0x40]),
+            Wasm::Loop => code.extend([0x03, 0x40]),
+            Wasm::If => code.extend([0x04, 0x40]),
+            Wasm::IfResult => code.extend([0x04, 0x7f]),
+            Wasm::Else => code.push(0x05),
+            Wasm::End => code.push(0x0b),
+            Wasm::Br(depth) => immediate(&mut code, 0x0c, depth),
+            Wasm::BrIf(depth) => immediate(&mut code, 0x0d, depth),
+            Wasm::Unreachable => code.push(0x00),
+            Wasm::Call(function) => immediate(&mut code, 0x10, function),
+            Wasm::LocalGet(local) => immediate(&mut code, 0x20, local),
+            Wasm::LocalTee(local) => immediate(&mut code, 0x22, local),
+            Wasm::Load => code.extend([load, align, 0]),
+            Wasm::Store => code.extend([store, align, 0]),
+            Wasm::Const(value) => {
+                code.push(0x41);
+                signed(&mut code, value)
+            }
+            Wasm::Eqz => code.push(0x45),
+            Wasm::LtS => code.push(0x48),
+            Wasm::GeU => code.push(0x4f),
+            Wasm::Add => code.push(0x6a),
+            Wasm::Mul => code.push(0x6c),
+            Wasm::And => code.push(0x71),
+        }
+    }
+    code.push(0x0b);
+
+    let mut module = b"\0asm\x01\0\0\0".to_vec();
+    // Types: () -> i32, (i32) -> (), () -> ()
+    section(
+        &mut module,
+        1,
+        &[3, 0x60, 0, 1, 0x7f, 0x60, 1, 0x7f, 0, 0x60, 0, 0],
+    );
+    let mut imports = vec![2];
+    for (name, ty) in [("read_byte", 0), ("write_byte", 1)] {
+        name_bytes(&mut imports, "env");
+        name_bytes(&mut imports, name);
+        imports.extend([0x00, ty]);
+    }
+    section(&mut module, 2, &imports);
+    section(&mut module, 3, &[1, 2]);
+    let mut memory = vec![1, 0x00];
+    unsigned(&mut memory, pages(options) as u32);
+    section(&mut module, 5, &memory);
+    let mut exports = vec![2];
+    name_bytes(&mut exports, "run");
+    exports.extend([0x00, 2]);
+    name_bytes(&mut exports, "memory");
+    exports.extend([0x02, 0]);
+    section(&mut module, 7, &exports);
+    let mut functions = vec![1];
+    unsigned(&mut functions, code.len() as u32);
+    functions.extend(code);
+    section(&mut module, 10, &functions);
+    Ok(module)
+}
+
+/// Number of bytes in a cell.
+fn cell_size(options: &Options) -> usize {
+    options.cell_width.bits() as usize / 8
+}
+
+fn pages(options: &Options) -> usize {
+    (options.tape_size.get() * cell_size(options))
+        .div_ceil(PAGE_SIZE)
+        .max(1)
+}
+
+/// An instruction with an index or depth.
+fn immediate(bytes: &mut Vec<u8>, opcode: u8, value: u32) {
+    bytes.push(opcode);
+    unsigned(bytes, value);
+}
+
+fn unsigned(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn signed(bytes: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn name_bytes(bytes: &mut Vec<u8>, name: &str) {
+    unsigned(bytes, name.len() as u32);
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+fn section(module: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    module.push(id);
+    unsigned(module, contents.len() as u32);
+    module.extend_from_slice(contents);
+}
+
+fn body(instructions: &[Instruction], options: &Options) -> Result<Vec<Wasm>, Error> {
+    if options
+        .tape_size
+        .get()
+        .checked_mul(cell_size(options))
+        .is_none_or(|size| size > 1 << 31)
+    {
+        return Err(Error::TapeTooLarge);
+    }
+    let mut code = Vec::new();
+    block(&mut code, &ir::lower(instructions, options.passes), options);
+    Ok(code)
+}
+
+fn block(code: &mut Vec<Wasm>, nodes: &[Node], options: &Options) {
+    let cell = [Wasm::LocalGet(POINTER), Wasm::Load];
+    // The blocks being generated, innermost last, and whether they are a loop.
+    let mut blocks = vec![(nodes.iter(), false)];
+    while let Some((block, is_loop)) = blocks.last_mut() {
+        let Some(node) = block.next() else {
+            if *is_loop {
+                code.extend([Wasm::Br(0), Wasm::End, Wasm::End]);
+            }
+            blocks.pop();
+            continue;
+        };
+        match &node.op {
+            Op::Add(amount) => {
+                code.push(Wasm::LocalGet(POINTER));
+                code.extend(cell);
+                code.extend([Wasm::Const(*amount as i32), Wasm::Add, Wasm::Store]);
+            }
+            Op::Move(amount) => move_by(code, *amount, options),
+            Op::Loop(body) => {
+                code.extend([Wasm::Block, Wasm::Loop]);
+                code.extend(cell);
+                code.extend([Wasm::Eqz, Wasm::BrIf(1)]);
+                blocks.push((body.iter(), true));
+            }
+            Op::Clear => code.extend([Wasm::LocalGet(POINTER), Wasm::Const(0), Wasm::Store]),
+            Op::Scan(amount) => {
+                code.extend([Wasm::Block, Wasm::Loop]);
+                code.extend(cell);
+                code.extend([Wasm::Eqz, Wasm::BrIf(1)]);
+                move_by(code, *amount, options);
+                code.extend([Wasm::Br(0), Wasm::End, Wasm::End]);
+            }
+            Op::Multiply(targets) => {
+                code.extend(cell);
+                code.extend([Wasm::LocalTee(VALUE), Wasm::If]);
+                for (offset, factor) in targets {
+                    move_by(code, *offset, options);
+                    code.push(Wasm::LocalGet(POINTER));
+                    code.extend(cell);
+                    code.extend([
+                        Wasm::LocalGet(VALUE),
+                        Wasm::Const(*factor as i32),
+                        Wasm::Mul,
+                        Wasm::Add,
+                        Wasm::Store,
+                    ]);
+                    move_by(code, -offset, options);
+                }
+                code.extend([
+                    Wasm::LocalGet(POINTER),
+                    Wasm::Const(0),
+                    Wasm::Store,
+                    Wasm::End,
+                ]);
+            }
+            Op::Input => {
+                code.extend([
+                    Wasm::LocalGet(POINTER),
+                    Wasm::Call(READ_BYTE),
+                    Wasm::LocalTee(BYTE),
+                    Wasm::Const(0),
+                    Wasm::LtS,
+                    Wasm::IfResult,
+                ]);
+                match options.eof {
+                    EofBehavior::Unchanged => code.extend(cell),
+                    eof => {
+                        let cell_type = CellType::new(options.cell_width, false, Overflow::Wrap);
+                        code.push(Wasm::Const(eof.apply(0, cell_type) as i32));
+                    }
+                }
+                code.extend([Wasm::Else, Wasm::LocalGet(BYTE), Wasm::End, Wasm::Store]);
+            }
+            Op::Output => {
+                code.extend(cell);
+                code.extend([Wasm::Const(0xff), Wasm::And, Wasm::Call(WRITE_BYTE)]);
+            }
+        }
+    }
+}
+
+/// Moves the pointer and traps if it left the tape. Amounts are clamped, as a
+/// move that big leaves the tape either way.
+fn move_by(code: &mut Vec<Wasm>, amount: isize, options: &Options) {
+    let bytes = (amount as i64 * cell_size(options) as i64).clamp(i32::MIN as i64, i32::MAX as i64);
+    code.extend([
+        Wasm::LocalGet(POINTER),
+        Wasm::Const(bytes as i32),
+        Wasm::Add,
+        Wasm::LocalTee(POINTER),
+        Wasm::Const((options.tape_size.get() * cell_size(options)) as i32),
+        Wasm::GeU,
+        Wasm::If,
+        Wasm::Unreachable,
+        Wasm::End,
+    ]);
+}
+
+#[test]
+fn matches_interpreter() {
+    use wasmi::{Caller, Engine, Linker, Module, Store};
+
+    struct Io {
+        input: std::vec::IntoIter<u8>,
+        output: Vec<u8>,
+    }
+
+    let run = |module: &[u8], input: &str| {
+        let engine = Engine::default();
+        let module = Module::new(&engine, module).unwrap();
+        let io = Io {
+            input: input.as_bytes().to_vec().into_iter(),
+            output: Vec::new(),
+        };
+        let mut store = Store::new(&engine, io);
+        let mut linker = Linker::new(&engine);
+        linker
+            .func_wrap("env", "read_byte", |mut caller: Caller<Io>| {
+                caller.data_mut().input.next().map_or(-1, i32::from)
+            })
+            .unwrap();
+        linker
+            .func_wrap("env", "write_byte", |mut caller: Caller<Io>, byte: i32| {
+                caller.data_mut().output.push(byte as u8)
+            })
+            .unwrap();
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let result = instance
+            .get_typed_func::<(), ()>(&store, "run")
+            .unwrap()
+            .call(&mut store, ());
+        (result.is_ok(), store.into_data().output)
+    };
+
+    let programs = [
+        (
+            include_str!("../../../brainfuck-example/fizzbuzz.bf"),
+            "",
+            EofBehavior::Unchanged,
+        ),
+        (",[>++++[<-------->-]<.,]", "hello", EofBehavior::Zero),
+        ("+++[>+++++<-]>[<+++++>-]<.>>+[<]", "", EofBehavior::Zero),
+        (",>,>,<<.>.>.", "a", EofBehavior::Unchanged),
+        (",>,>,<<.>.>.", "a", EofBehavior::MaxValue),
+    ];
+    for (source, input, eof) in programs {
+        for cell_width in [CellWidth::Bits8, CellWidth::Bits16, CellWidth::Bits32] {
+            let options = Options {
+                cell_width,
+                eof,
+                ..Options::default()
+            };
+            let instructions = parser::Parser::new(lexer::lex(source)).parse().unwrap();
+            let mut expected = Vec::new();
+            let result = crate::InterpreterBuilder::new()
+                .cell_width(cell_width)
+                .eof(eof)
+                .build(input.as_bytes(), &mut expected)
+                .interpret(&instructions);
+            let expected = (result.is_ok(), expected);
+
+            let wasm = generate_wasm(&instructions, &options).unwrap();
+            assert_eq!(run(&wasm, input), expected);
+            let wat = wat::parse_str(generate_wat(&instructions, &options).unwrap()).unwrap();
+            assert_eq!(run(&wat, input), expected);
+        }
+    }
+}
+
+#[test]
+fn tape_too_large() {
+    let options = Options {
+        tape_size: std::num::NonZeroUsize::new(1 << 30).unwrap(),
+        cell_width: CellWidth::Bits32,
+        ..Options::default()
+    };
+    assert_eq!(generate_wasm(&[], &options), Err(Error::TapeTooLarge));
+    assert_eq!(generate_wat(&[], &options), Err(Error::TapeTooLarge));
+}
+
+#[test]
+fn deep_nesting() {
+    let depth = 100_000;
+    let source = format!("+{}{}.", "[-".repeat(depth), "]".repeat(depth));
+    let instructions = parser::Parser::new(lexer::lex(&source)).parse().unwrap();
+    assert!(generate_wasm(&instructions, &Options::default()).is_ok());
+}
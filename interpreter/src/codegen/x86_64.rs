@@ -0,0 +1,198 @@
+//! Encodes the optimised program as x86-64 machine code.
+//!
+//! The code keeps the base of the tape in r12, the pointer in r13 and the value
+//! of a multiply loop in r15. Cells are 32 bits wide in memory and masked to
+//! the cell width after every change. How the pointer is moved and how input
+//! and output are done is up to the [`Runtime`].
+
+use lexer::Span;
+use parser::ir::{Node, Op};
+
+pub(crate) const JE: u8 = 0x84;
+pub(crate) const JNE: u8 = 0x85;
+pub(crate) const JB: u8 = 0x82;
+pub(crate) const JAE: u8 = 0x83;
+pub(crate) const JS: u8 = 0x88;
+
+/// `[r12 + r13 * 4]` as ModRM and SIB bytes for the given `reg` field.
+const fn cell(reg: u8) -> [u8; 2] {
+    [(reg << 3) | 0b100, 0b10_101_100]
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Assembler {
+    pub(crate) code: Vec<u8>,
+    mask: u32,
+}
+
+/// Emits the parts of a program that depend on where it runs.
+pub(crate) trait Runtime {
+    /// Moves r13 by `amount`, handling the edges of the tape.
+    fn move_by(&mut self, assembler: &mut Assembler, amount: i32, span: Span);
+    fn input(&mut self, assembler: &mut Assembler, span: Span);
+    fn output(&mut self, assembler: &mut Assembler, span: Span);
+}
+
+impl Assembler {
+    pub(crate) fn new(mask: u32) -> Self {
+        Assembler {
+            code: Vec::new(),
+            mask,
+        }
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.code.len()
+    }
+
+    pub(crate) fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn emit_u32(&mut self, value: u32) {
+        self.emit(&value.to_le_bytes());
+    }
+
+    pub(crate) fn emit_u64(&mut self, value: u64) {
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// Emits a conditional jump and returns the position of its offset.
+    pub(crate) fn jump_if(&mut self, condition: u8) -> usize {
+        self.emit(&[0x0f, condition]);
+        self.emit_u32(0);
+        self.position() - 4
+    }
+
+    /// Emits a jump and returns the position of its offset.
+    pub(crate) fn jump(&mut self) -> usize {
+        self.emit(&[0xe9]);
+        self.emit_u32(0);
+        self.position() - 4
+    }
+
+    /// Points the jump offset at `at` to `target`.
+    pub(crate) fn patch(&mut self, at: usize, target: usize) {
+        let offset = target as i64 - (at as i64 + 4);
+        self.code[at..at + 4].copy_from_slice(&(offset as i32).to_le_bytes());
+    }
+
+    /// Points the jump offset at `at` to the current position.
+    pub(crate) fn patch_here(&mut self, at: usize) {
+        self.patch(at, self.position());
+    }
+
+    /// `lea rax, [r13 + amount]`, the pointer after a move.
+    pub(crate) fn next_pointer(&mut self, amount: i32) {
+        self.emit(&[0x49, 0x8d, 0x85]);
+        self.emit(&amount.to_le_bytes());
+    }
+
+    /// `mov r13, rax`
+    pub(crate) fn set_pointer(&mut self) {
+        self.emit(&[0x49, 0x89, 0xc5]);
+    }
+
+    /// `lea rsi, [r12 + r13 * 4]`, the address of the current cell.
+    pub(crate) fn cell_address(&mut self) {
+        self.emit(&[0x4b, 0x8d, 0x34, 0xac]);
+    }
+
+    /// `mov dword [cell], value`
+    pub(crate) fn store(&mut self, value: u32) {
+        self.emit(&[0x43, 0xc7]);
+        self.emit(&cell(0));
+        self.emit_u32(value);
+    }
+
+    /// `mov dword [cell], eax`
+    pub(crate) fn store_eax(&mut self) {
+        self.emit(&[0x43, 0x89]);
+        self.emit(&cell(0));
+    }
+
+    /// `cmp dword [cell], 0`
+    fn compare_cell(&mut self) {
+        self.emit(&[0x43, 0x83]);
+        self.emit(&cell(7));
+        self.emit(&[0x00]);
+    }
+
+    /// `and dword [cell], mask` unless cells are 32 bits wide.
+    fn mask_cell(&mut self) {
+        if self.mask != u32::MAX {
+            self.emit(&[0x43, 0x81]);
+            self.emit(&cell(4));
+            self.emit_u32(self.mask);
+        }
+    }
+
+    /// Emits the nodes, or returns `None` if a move doesn't fit into 32 bits.
+    pub(crate) fn block(&mut self, nodes: &[Node], runtime: &mut impl Runtime) -> Option<()> {
+        // The blocks being emitted, with the jump over their loop and where the
+        // loop body starts.
+        let mut blocks = vec![(nodes.iter(), None)];
+        while let Some((block, jumps)) = blocks.last_mut() {
+            let Some(node) = block.next() else {
+                if let Some((skip, start)) = *jumps {
+                    self.compare_cell();
+                    let back = self.jump_if(JNE);
+                    self.patch(back, start);
+                    self.patch_here(skip);
+                }
+                blocks.pop();
+                continue;
+            };
+            match &node.op {
+                Op::Add(amount) => {
+                    // add dword [cell], amount
+                    self.emit(&[0x43, 0x81]);
+                    self.emit(&cell(0));
+                    self.emit_u32(*amount as u32);
+                    self.mask_cell();
+                }
+                Op::Move(amount) => runtime.move_by(self, i32::try_from(*amount).ok()?, node.span),
+                Op::Loop(body) => {
+                    self.compare_cell();
+                    let skip = self.jump_if(JE);
+                    let start = self.position();
+                    blocks.push((body.iter(), Some((skip, start))));
+                }
+                Op::Clear => self.store(0),
+                Op::Scan(amount) => {
+                    let amount = i32::try_from(*amount).ok()?;
+                    let start = self.position();
+                    self.compare_cell();
+                    let end = self.jump_if(JE);
+                    runtime.move_by(self, amount, node.span);
+                    let back = self.jump();
+                    self.patch(back, start);
+                    self.patch_here(end);
+                }
+                Op::Multiply(targets) => {
+                    // mov r15d, dword [cell]; test r15d, r15d; je skip
+                    self.emit(&[0x47, 0x8b]);
+                    self.emit(&cell(7));
+                    self.emit(&[0x45, 0x85, 0xff]);
+                    let skip = self.jump_if(JE);
+                    for (offset, factor) in targets {
+                        let offset = i32::try_from(*offset).ok()?;
+                        runtime.move_by(self, offset, node.span);
+                        // imul ecx, r15d, factor; add dword [cell], ecx
+                        self.emit(&[0x41, 0x69, 0xcf]);
+                        self.emit_u32(*factor as u32);
+                        self.emit(&[0x43, 0x01]);
+                        self.emit(&cell(1));
+                        self.mask_cell();
+                        runtime.move_by(self, -offset, node.span);
+                    }
+                    self.store(0);
+                    self.patch_here(skip);
+                }
+                Op::Input => runtime.input(self, node.span),
+                Op::Output => runtime.output(self, node.span),
+            }
+        }
+        Some(())
+    }
+}
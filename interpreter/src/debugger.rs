@@ -0,0 +1,306 @@
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+
+use lexer::{lex, LexerToken, Span, TokenType};
+use parser::{Instruction, Parser, ParserError, SpanTable};
+
+use crate::{Interpreter, RuntimeResult, Tape};
+
+/// Why the [`Debugger`] stopped running the program.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Status {
+    /// The requested steps are done.
+    Paused,
+    /// The next instruction has a breakpoint.
+    Breakpoint,
+    /// There are no instructions left.
+    Finished,
+}
+
+/// Runs a program one command at a time.
+///
+/// Unlike the [`Interpreter`], the debugger doesn't optimise the program, so
+/// every step executes exactly one command of the source. Breakpoints are set
+/// by byte offset in the source and stop the program before the first command
+/// at or after that offset. Every `#` in the source is a breakpoint.
+pub struct Debugger<R, W> {
+    interpreter: Interpreter<R, W>,
+    commands: Vec<(TokenType, Span)>,
+    /// The index of the matching bracket for every bracket.
+    jumps: Vec<usize>,
+    /// The index of the next command to execute.
+    next: usize,
+    breakpoints: BTreeSet<usize>,
+    /// Whether the debugger already stopped before the next command, so that
+    /// running on doesn't stop at its breakpoint again.
+    stopped: bool,
+}
+
+impl<R: Read, W: Write> Debugger<R, W> {
+    /// Prepares `source` to run on `interpreter`, using its tape, cells and I/O.
+    pub fn new(interpreter: Interpreter<R, W>, source: &str) -> Result<Self, Vec<ParserError>> {
+        Self::with_tokens(interpreter, source, lex(source))
+    }
+
+    /// Like [`new`](Self::new), but with the commands of `source` already
+    /// lexed, for programs that aren't written in plain brainfuck.
+    pub fn with_tokens(
+        interpreter: Interpreter<R, W>,
+        source: &str,
+        tokens: Vec<LexerToken>,
+    ) -> Result<Self, Vec<ParserError>> {
+        let (instructions, spans) = Parser::new(tokens).parse_with_spans()?;
+        let mut debugger = Debugger {
+            interpreter,
+            commands: Vec::with_capacity(spans.len()),
+            jumps: Vec::with_capacity(spans.len()),
+            next: 0,
+            breakpoints: BTreeSet::new(),
+            stopped: false,
+        };
+        debugger.flatten(&instructions, &spans);
+        for (offset, _) in source.match_indices('#') {
+            debugger.add_breakpoint(offset);
+        }
+        Ok(debugger)
+    }
+
+    /// Appends the instructions as commands. The index of a command is its
+    /// position in `spans`.
+    fn flatten(&mut self, instructions: &[Instruction], spans: &SpanTable) {
+        let span = |position| {
+            spans
+                .get(position)
+                .expect("the parser keeps a span for every position")
+        };
+        // The blocks being flattened, with the index of the `[` of their loop.
+        let mut blocks = vec![(instructions.iter(), None)];
+        while let Some((block, open)) = blocks.last_mut() {
+            let Some(instruction) = block.next() else {
+                if let Some(open) = *open {
+                    let end = self.commands.len();
+                    self.commands.push((TokenType::CloseLoop, span(end)));
+                    self.jumps.push(open);
+                    self.jumps[open] = end;
+                }
+                blocks.pop();
+                continue;
+            };
+            let position = self.commands.len();
+            let command = match instruction {
+                Instruction::Add => TokenType::Add,
+                Instruction::Subtract => TokenType::Subtract,
+                Instruction::Left => TokenType::Left,
+                Instruction::Right => TokenType::Right,
+                Instruction::Input => TokenType::Input,
+                Instruction::Output => TokenType::Output,
+                Instruction::Loop(body) => {
+                    self.commands.push((TokenType::OpenLoop, span(position)));
+                    self.jumps.push(0);
+                    blocks.push((body.iter(), Some(position)));
+                    continue;
+                }
+            };
+            self.commands.push((command, span(position)));
+            self.jumps.push(0);
+        }
+    }
+
+    pub fn interpreter(&self) -> &Interpreter<R, W> {
+        &self.interpreter
+    }
+
+    pub fn tape(&self) -> &Tape {
+        self.interpreter.tape()
+    }
+
+    /// The span of the next command, or `None` if the program is finished.
+    pub fn position(&self) -> Option<Span> {
+        self.commands.get(self.next).map(|(_, span)| *span)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.commands.len()
+    }
+
+    /// Sets a breakpoint on the first command at or after `offset`. Returns the
+    /// span of that command, or `None` if there is none.
+    pub fn add_breakpoint(&mut self, offset: usize) -> Option<Span> {
+        let index = self.command_at(offset)?;
+        self.breakpoints.insert(index);
+        Some(self.commands[index].1)
+    }
+
+    /// Removes the breakpoint [`add_breakpoint`](Self::add_breakpoint) set for
+    /// `offset`. Returns whether there was one.
+    pub fn remove_breakpoint(&mut self, offset: usize) -> bool {
+        self.command_at(offset)
+            .is_some_and(|index| self.breakpoints.remove(&index))
+    }
+
+    /// The spans of the commands with a breakpoint.
+    pub fn breakpoints(&self) -> impl Iterator<Item = Span> + '_ {
+        self.breakpoints.iter().map(|&index| self.commands[index].1)
+    }
+
+    fn command_at(&self, offset: usize) -> Option<usize> {
+        let index = self
+            .commands
+            .partition_point(|(_, span)| span.from < offset);
+        (index < self.commands.len()).then_some(index)
+    }
+
+    /// Executes a single command.
+    pub fn step(&mut self) -> RuntimeResult<Status> {
+        let status = self.execute()?;
+        self.stopped = true;
+        Ok(status)
+    }
+
+    fn execute(&mut self) -> RuntimeResult<Status> {
+        let Some(&(command, span)) = self.commands.get(self.next) else {
+            return Ok(Status::Finished);
+        };
+        let interpreter = &mut self.interpreter;
+        let result = match command {
+            TokenType::Add => interpreter.add(1),
+            TokenType::Subtract => interpreter.add(-1),
+            TokenType::Left => interpreter.move_by(-1),
+            TokenType::Right => interpreter.move_by(1),
+            TokenType::Input => interpreter.input(),
+            TokenType::Output => interpreter.output(),
+            TokenType::OpenLoop => {
+                if interpreter.tape.get() == 0 {
+                    self.next = self.jumps[self.next];
+                }
+                Ok(())
+            }
+            TokenType::CloseLoop => {
+                if interpreter.tape.get() != 0 {
+                    self.next = self.jumps[self.next];
+                }
+                Ok(())
+            }
+        };
+        result.map_err(|trap| trap.at(span))?;
+        self.next += 1;
+        self.stopped = false;
+        Ok(if self.is_finished() {
+            Status::Finished
+        } else {
+            Status::Paused
+        })
+    }
+
+    /// Executes the next command, or the whole loop if the next command starts
+    /// one.
+    pub fn step_over(&mut self) -> RuntimeResult<Status> {
+        match self.commands.get(self.next) {
+            Some((TokenType::OpenLoop, _)) => {
+                let end = self.jumps[self.next] + 1;
+                self.run_until(|debugger| debugger.next == end)
+            }
+            _ => self.step(),
+        }
+    }
+
+    /// Runs until the innermost loop around the next command is left, or to the
+    /// end if there is no loop around it.
+    pub fn step_out(&mut self) -> RuntimeResult<Status> {
+        let mut depth = 0usize;
+        let end = self.commands[self.next.min(self.commands.len())..]
+            .iter()
+            .position(|(command, _)| {
+                match command {
+                    TokenType::OpenLoop => depth += 1,
+                    TokenType::CloseLoop if depth == 0 => return true,
+                    TokenType::CloseLoop => depth -= 1,
+                    _ => {}
+                }
+                false
+            })
+            .map(|offset| self.next + offset + 1);
+        match end {
+            Some(end) => self.run_until(|debugger| debugger.next == end),
+            None => self.run(),
+        }
+    }
+
+    /// Runs until the next breakpoint or the end of the program.
+    pub fn run(&mut self) -> RuntimeResult<Status> {
+        self.run_until(|_| false)
+    }
+
+    /// Steps until a breakpoint is hit, or at least once and until `done` is
+    /// true. A breakpoint on the next command is hit unless the debugger
+    /// already stopped there.
+    fn run_until(&mut self, done: impl Fn(&Self) -> bool) -> RuntimeResult<Status> {
+        let mut stepped = false;
+        let status = loop {
+            if !self.stopped && self.breakpoints.contains(&self.next) {
+                break Status::Breakpoint;
+            }
+            if stepped && done(self) {
+                break Status::Paused;
+            }
+            if self.execute()? == Status::Finished {
+                break Status::Finished;
+            }
+            stepped = true;
+        };
+        self.stopped = true;
+        Ok(status)
+    }
+}
+
+#[test]
+fn stepping() {
+    let interpreter = Interpreter::new(std::io::empty(), Vec::new());
+    let mut debugger = Debugger::new(interpreter, "++[->+<]# >.").unwrap();
+    assert_eq!(debugger.step().unwrap(), Status::Paused);
+    assert_eq!(debugger.tape().get(), 1);
+    debugger.step().unwrap();
+    assert_eq!(debugger.position(), Some(Span::from(2, 3)));
+
+    assert_eq!(debugger.step_over().unwrap(), Status::Breakpoint);
+    assert_eq!(debugger.position(), Some(Span::from(10, 11)));
+    assert_eq!(debugger.tape().cells()[..2], [0, 2]);
+    assert_eq!(debugger.run().unwrap(), Status::Finished);
+    assert_eq!(debugger.interpreter().output, [2]);
+
+    let interpreter = Interpreter::new(std::io::empty(), std::io::sink());
+    let mut debugger = Debugger::new(interpreter, "+++[>+<-]>>").unwrap();
+    assert_eq!(debugger.add_breakpoint(4), Some(Span::from(4, 5)));
+    assert_eq!(debugger.run().unwrap(), Status::Breakpoint);
+    assert!(debugger.remove_breakpoint(4));
+    assert_eq!(debugger.step_out().unwrap(), Status::Paused);
+    assert_eq!(debugger.position(), Some(Span::from(9, 10)));
+    assert_eq!(debugger.tape().cells()[..2], [0, 3]);
+
+    let interpreter = Interpreter::new(std::io::empty(), Vec::new());
+    let mut debugger = Debugger::new(interpreter, "# +. #.").unwrap();
+    assert_eq!(debugger.run().unwrap(), Status::Breakpoint);
+    assert_eq!(debugger.position(), Some(Span::from(2, 3)));
+    debugger.step().unwrap();
+    debugger.step().unwrap();
+    assert_eq!(debugger.position(), Some(Span::from(6, 7)));
+    assert_eq!(debugger.run().unwrap(), Status::Finished);
+    assert_eq!(debugger.interpreter().output, [1, 1]);
+
+    let depth = 100_000;
+    let source = format!("+{}-{}.", "[".repeat(depth), "]".repeat(depth));
+    let interpreter = Interpreter::new(std::io::empty(), Vec::new());
+    let mut debugger = Debugger::new(interpreter, &source).unwrap();
+    assert_eq!(debugger.run().unwrap(), Status::Finished);
+    assert_eq!(debugger.interpreter().output, [0]);
+
+    let interpreter = Interpreter::new(std::io::empty(), std::io::sink());
+    let mut debugger = Debugger::new(interpreter, "<").unwrap();
+    assert!(matches!(
+        debugger.run(),
+        Err(crate::RuntimeError::PointerUnderflow(Span {
+            from: 0,
+            to: 1
+        }))
+    ));
+}
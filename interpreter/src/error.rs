@@ -0,0 +1,150 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::io;
+use std::path::PathBuf;
+
+use lexer::Span;
+
+use error_messages::Diagnostic;
+use parser::ParserError;
+
+use crate::cell::CellError;
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// A `<` tried to move the pointer below the first cell.
+    PointerUnderflow(Span),
+    /// A `>` tried to move the pointer past the last cell.
+    PointerOverflow(Span),
+    /// A `+` made a cell exceed its largest value with [`Overflow::Error`](crate::Overflow::Error).
+    CellOverflow(Span),
+    /// A `-` made a cell go below its smallest value with [`Overflow::Error`](crate::Overflow::Error).
+    CellUnderflow(Span),
+    /// Reading from the input or writing to the output failed.
+    Io(io::Error, Span),
+    /// The program file could not be read.
+    FileRead(PathBuf, io::Error),
+    /// The program has unmatched brackets.
+    Parser(Vec<ParserError>),
+}
+
+impl RuntimeError {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::PointerUnderflow(span)
+            | Self::PointerOverflow(span)
+            | Self::CellOverflow(span)
+            | Self::CellUnderflow(span)
+            | Self::Io(_, span) => Some(*span),
+            Self::FileRead(..) => None,
+            Self::Parser(errors) => errors.first().map(ParserError::span),
+        }
+    }
+
+    /// Describes the error for people, with one diagnostic per parser error.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let diagnostic = match self {
+            Self::PointerUnderflow(span) => Diagnostic::error("Pointer moved below the first cell")
+                .with_label(*span, "this moved the pointer off the tape"),
+            Self::PointerOverflow(span) => Diagnostic::error("Pointer moved past the last cell")
+                .with_label(*span, "this moved the pointer off the tape")
+                .with_help("use a larger tape"),
+            Self::CellOverflow(span) => Diagnostic::error("Cell overflowed")
+                .with_label(*span, "this made the cell exceed its largest value")
+                .with_note("cells don't wrap around on overflow"),
+            Self::CellUnderflow(span) => Diagnostic::error("Cell underflowed")
+                .with_label(*span, "this made the cell go below its smallest value")
+                .with_note("cells don't wrap around on overflow"),
+            Self::Io(err, span) => Diagnostic::error(format!("I/O error: {}", err))
+                .with_label(*span, "while running this"),
+            Self::FileRead(path, err) => {
+                Diagnostic::error(format!("Error while reading {}: {}", path.display(), err))
+            }
+            Self::Parser(errors) => return errors.iter().map(Diagnostic::from).collect(),
+        };
+        vec![diagnostic]
+    }
+}
+
+/// A failure that is not yet tied to the instruction that caused it.
+#[derive(Debug)]
+pub(crate) enum Trap {
+    PointerUnderflow,
+    PointerOverflow,
+    CellOverflow,
+    CellUnderflow,
+    Io(io::Error),
+}
+
+impl Trap {
+    pub(crate) fn at(self, span: Span) -> RuntimeError {
+        match self {
+            Self::PointerUnderflow => RuntimeError::PointerUnderflow(span),
+            Self::PointerOverflow => RuntimeError::PointerOverflow(span),
+            Self::CellOverflow => RuntimeError::CellOverflow(span),
+            Self::CellUnderflow => RuntimeError::CellUnderflow(span),
+            Self::Io(err) => RuntimeError::Io(err, span),
+        }
+    }
+}
+
+impl From<CellError> for Trap {
+    fn from(err: CellError) -> Self {
+        match err {
+            CellError::Overflow => Self::CellOverflow,
+            CellError::Underflow => Self::CellUnderflow,
+        }
+    }
+}
+
+impl From<Vec<ParserError>> for RuntimeError {
+    fn from(errors: Vec<ParserError>) -> Self {
+        Self::Parser(errors)
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PointerUnderflow(span) => {
+                write!(f, "pointer moved below the first cell at {}", span.from)
+            }
+            Self::PointerOverflow(span) => {
+                write!(f, "pointer moved past the last cell at {}", span.from)
+            }
+            Self::CellOverflow(span) => write!(f, "cell overflowed at {}", span.from),
+            Self::CellUnderflow(span) => write!(f, "cell underflowed at {}", span.from),
+            Self::Io(err, span) => write!(f, "I/O error at {}: {}", span.from, err),
+            Self::FileRead(path, err) => {
+                write!(f, "error while reading {}: {}", path.display(), err)
+            }
+            Self::Parser(errors) => {
+                for (i, err) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    match err {
+                        ParserError::UnexpectedEOF(span) => {
+                            write!(f, "end of file before loop close at {}", span.from)?
+                        }
+                        ParserError::UnexpectedClosing(span) => {
+                            write!(f, "unexpected closing of loop at {}", span.from)?
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err, _) | Self::FileRead(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::io;
+use std::io::Read;
+
+use crate::cell::CellType;
+
+/// What `,` does to the current cell once the input has ended.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum EofBehavior {
+    /// Leave the cell as it is. This is the default.
+    #[default]
+    Unchanged,
+    /// Set the cell to 0.
+    Zero,
+    /// Set every bit of the cell, which is 255 for unsigned 8-bit cells and -1
+    /// for signed ones.
+    MaxValue,
+}
+
+impl EofBehavior {
+    /// The new value of a cell holding `raw` after reading past the end of input.
+    pub fn apply(self, raw: u32, cell_type: CellType) -> u32 {
+        match self {
+            Self::Unchanged => raw,
+            Self::Zero => 0,
+            Self::MaxValue => cell_type.mask(),
+        }
+    }
+}
+
+/// Reads a single byte, or `None` at the end of input.
+pub(crate) fn read_input(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut buf = [0; 1];
+    match input.read_exact(&mut buf) {
+        Ok(()) => Ok(Some(buf[0])),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[test]
+fn eof_behavior() {
+    let byte = CellType::default();
+    let signed = CellType::new(crate::CellWidth::Bits16, true, crate::Overflow::Wrap);
+    assert_eq!(EofBehavior::Unchanged.apply(42, byte), 42);
+    assert_eq!(EofBehavior::Zero.apply(42, byte), 0);
+    assert_eq!(EofBehavior::MaxValue.apply(42, byte), 255);
+    assert_eq!(signed.value(EofBehavior::MaxValue.apply(42, signed)), -1);
+}
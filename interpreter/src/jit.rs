@@ -0,0 +1,333 @@
+//! Compiles the optimised program to x86-64 machine code.
+//!
+//! The generated code (see [`codegen::x86_64`](crate::codegen::x86_64)) keeps
+//! the base of the tape, the pointer and the length of the tape in registers and
+//! works on the cells directly. Moves that leave the
+//! tape, input and output call back into the interpreter, so edges and I/O
+//! behave exactly as they do in the other backends. Cells that don't wrap
+//! around and other targets fall back to the bytecode VM.
+
+use std::io::{Read, Write};
+
+use lexer::Span;
+use parser::ir::Node;
+
+use crate::error::Trap;
+use crate::vm::Bytecode;
+use crate::{Interpreter, Overflow};
+
+pub(crate) fn run<R: Read, W: Write>(
+    interpreter: &mut Interpreter<R, W>,
+    nodes: &[Node],
+) -> Result<(), (Span, Trap)> {
+    #[cfg(all(target_arch = "x86_64", unix))]
+    if interpreter.cell_type.overflow == Overflow::Wrap {
+        if let Some(function) = x86_64::compile::<R, W>(nodes, interpreter.cell_type.mask()) {
+            return function.call(interpreter);
+        }
+    }
+    interpreter.execute(&Bytecode::compile(nodes))
+}
+
+#[cfg(all(target_arch = "x86_64", unix))]
+mod x86_64 {
+    use std::io::{Read, Write};
+    use std::marker::PhantomData;
+    use std::{mem, ptr};
+
+    use lexer::Span;
+    use parser::ir::Node;
+
+    use crate::codegen::x86_64::{Assembler, Runtime, JB, JNE};
+    use crate::error::Trap;
+    use crate::Interpreter;
+
+    /// The state shared between the machine code and the callbacks. The
+    /// generated code relies on the offsets of the first three fields.
+    #[repr(C)]
+    struct Context<R, W> {
+        cells: *mut u32,
+        len: usize,
+        pointer: usize,
+        interpreter: *mut Interpreter<R, W>,
+        trap: Option<(u32, Trap)>,
+    }
+
+    impl<R, W> Context<R, W> {
+        /// Runs `f` on the interpreter with its tape pointer in sync and
+        /// reloads the tape afterwards, as it may have grown.
+        unsafe fn with_interpreter(
+            &mut self,
+            site: u32,
+            f: impl FnOnce(&mut Interpreter<R, W>) -> Result<(), Trap>,
+        ) -> u64 {
+            let interpreter = &mut *self.interpreter;
+            interpreter.tape.set_pointer(self.pointer);
+            let result = f(interpreter);
+            self.cells = interpreter.tape.as_mut_ptr();
+            self.len = interpreter.tape.cells().len();
+            self.pointer = interpreter.tape.pointer();
+            match result {
+                Ok(()) => 0,
+                Err(trap) => {
+                    self.trap = Some((site, trap));
+                    1
+                }
+            }
+        }
+    }
+
+    extern "C" fn move_by<R: Read, W: Write>(
+        context: *mut Context<R, W>,
+        amount: i64,
+        site: u32,
+    ) -> u64 {
+        unsafe {
+            (*context).with_interpreter(site, |interpreter| interpreter.move_by(amount as isize))
+        }
+    }
+
+    extern "C" fn input<R: Read, W: Write>(context: *mut Context<R, W>, _: i64, site: u32) -> u64 {
+        unsafe { (*context).with_interpreter(site, |interpreter| interpreter.input()) }
+    }
+
+    extern "C" fn output<R: Read, W: Write>(context: *mut Context<R, W>, _: i64, site: u32) -> u64 {
+        unsafe { (*context).with_interpreter(site, |interpreter| interpreter.output()) }
+    }
+
+    type Callback<R, W> = extern "C" fn(*mut Context<R, W>, i64, u32) -> u64;
+
+    /// Executable machine code for one program.
+    pub(super) struct Function<R, W> {
+        memory: *mut libc::c_void,
+        size: usize,
+        spans: Vec<Span>,
+        interpreter: PhantomData<fn(&mut Interpreter<R, W>)>,
+    }
+
+    impl<R: Read, W: Write> Function<R, W> {
+        pub(super) fn call(&self, interpreter: &mut Interpreter<R, W>) -> Result<(), (Span, Trap)> {
+            let mut context = Context {
+                cells: interpreter.tape.as_mut_ptr(),
+                len: interpreter.tape.cells().len(),
+                pointer: interpreter.tape.pointer(),
+                interpreter,
+                trap: None,
+            };
+            let entry: extern "C" fn(*mut Context<R, W>) -> u64 =
+                unsafe { mem::transmute(self.memory) };
+            let status = entry(&mut context);
+            let interpreter = unsafe { &mut *context.interpreter };
+            interpreter.tape.set_pointer(context.pointer);
+
+            match (status, context.trap) {
+                (0, _) => Ok(()),
+                (_, Some((site, trap))) => Err((self.spans[site as usize], trap)),
+                (_, None) => unreachable!("the machine code failed without a trap"),
+            }
+        }
+    }
+
+    impl<R, W> Drop for Function<R, W> {
+        fn drop(&mut self) {
+            unsafe {
+                libc::munmap(self.memory, self.size);
+            }
+        }
+    }
+
+    /// Compiles the nodes for cells with the given mask, or returns `None` if an
+    /// amount doesn't fit into an immediate or no executable memory is available.
+    pub(super) fn compile<R: Read, W: Write>(nodes: &[Node], mask: u32) -> Option<Function<R, W>> {
+        let mut assembler = Assembler::new(mask);
+        let mut runtime = Callbacks::<R, W> {
+            spans: Vec::new(),
+            error_exits: Vec::new(),
+            context: PhantomData,
+        };
+        runtime.prologue(&mut assembler);
+        assembler.block(nodes, &mut runtime)?;
+        runtime.epilogue(&mut assembler);
+
+        let size = assembler.code.len();
+        unsafe {
+            let memory = libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if memory == libc::MAP_FAILED {
+                return None;
+            }
+            ptr::copy_nonoverlapping(assembler.code.as_ptr(), memory as *mut u8, size);
+            let function = Function {
+                memory,
+                size,
+                spans: runtime.spans,
+                interpreter: PhantomData,
+            };
+            if libc::mprotect(memory, size, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return None;
+            }
+            Some(function)
+        }
+    }
+
+    // Besides the registers used by the assembler, rbx holds the context and
+    // r14 the length of the tape.
+
+    /// Leaves the edges and I/O to the interpreter through callbacks.
+    struct Callbacks<R, W> {
+        spans: Vec<Span>,
+        /// Jumps to patch with the address of the error exit.
+        error_exits: Vec<usize>,
+        context: PhantomData<fn(*mut Context<R, W>)>,
+    }
+
+    impl<R: Read, W: Write> Callbacks<R, W> {
+        fn site(&mut self, span: Span) -> u32 {
+            self.spans.push(span);
+            (self.spans.len() - 1) as u32
+        }
+
+        fn prologue(&mut self, assembler: &mut Assembler) {
+            // push rbx; push r12; push r13; push r14; push r15; mov rbx, rdi
+            assembler.emit(&[0x53, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]);
+            assembler.emit(&[0x48, 0x89, 0xfb]);
+            self.reload(assembler);
+        }
+
+        fn epilogue(&mut self, assembler: &mut Assembler) {
+            // mov [rbx + 16], r13; xor eax, eax
+            assembler.emit(&[0x4c, 0x89, 0x6b, 0x10, 0x31, 0xc0]);
+            let done = assembler.jump();
+            let error_exit = assembler.position();
+            // mov eax, 1
+            assembler.emit(&[0xb8]);
+            assembler.emit_u32(1);
+            assembler.patch_here(done);
+            // pop r15; pop r14; pop r13; pop r12; pop rbx; ret
+            assembler.emit(&[0x41, 0x5f, 0x41, 0x5e, 0x41, 0x5d, 0x41, 0x5c, 0x5b, 0xc3]);
+            for at in mem::take(&mut self.error_exits) {
+                assembler.patch(at, error_exit);
+            }
+        }
+
+        /// Loads the tape from the context into the registers.
+        fn reload(&mut self, assembler: &mut Assembler) {
+            // mov r12, [rbx]; mov r14, [rbx + 8]; mov r13, [rbx + 16]
+            assembler.emit(&[
+                0x4c, 0x8b, 0x23, 0x4c, 0x8b, 0x73, 0x08, 0x4c, 0x8b, 0x6b, 0x10,
+            ]);
+        }
+
+        fn call(
+            &mut self,
+            assembler: &mut Assembler,
+            callback: Callback<R, W>,
+            argument: i64,
+            span: Span,
+        ) {
+            let site = self.site(span);
+            // mov [rbx + 16], r13; mov rdi, rbx
+            assembler.emit(&[0x4c, 0x89, 0x6b, 0x10, 0x48, 0x89, 0xdf]);
+            // mov rsi, argument; mov edx, site
+            assembler.emit(&[0x48, 0xbe]);
+            assembler.emit(&argument.to_le_bytes());
+            assembler.emit(&[0xba]);
+            assembler.emit_u32(site);
+            // mov rax, callback; call rax
+            assembler.emit(&[0x48, 0xb8]);
+            assembler.emit_u64(callback as usize as u64);
+            assembler.emit(&[0xff, 0xd0]);
+            // test rax, rax; jnz error_exit
+            assembler.emit(&[0x48, 0x85, 0xc0]);
+            let exit = assembler.jump_if(JNE);
+            self.error_exits.push(exit);
+            self.reload(assembler);
+        }
+    }
+
+    impl<R: Read, W: Write> Runtime for Callbacks<R, W> {
+        fn move_by(&mut self, assembler: &mut Assembler, amount: i32, span: Span) {
+            // lea rax, [r13 + amount]; cmp rax, r14; jb inside
+            assembler.next_pointer(amount);
+            assembler.emit(&[0x4c, 0x39, 0xf0]);
+            let inside = assembler.jump_if(JB);
+            self.call(assembler, move_by::<R, W>, amount as i64, span);
+            let done = assembler.jump();
+            assembler.patch_here(inside);
+            assembler.set_pointer();
+            assembler.patch_here(done);
+        }
+
+        fn input(&mut self, assembler: &mut Assembler, span: Span) {
+            self.call(assembler, input::<R, W>, 0, span);
+        }
+
+        fn output(&mut self, assembler: &mut Assembler, span: Span) {
+            self.call(assembler, output::<R, W>, 0, span);
+        }
+    }
+}
+
+#[test]
+fn matches_bytecode() {
+    use crate::{Backend, CellWidth, Edge, EofBehavior, InterpreterBuilder};
+
+    let run = |program: &str, builder: InterpreterBuilder| {
+        let results = [Backend::Jit, Backend::Bytecode].map(|backend| {
+            let mut interpreter = builder
+                .clone()
+                .backend(backend)
+                .build("jit".as_bytes(), Vec::new());
+            let result = interpreter
+                .interpret_source(program)
+                .map_err(|err| err.span());
+            (
+                result,
+                interpreter.tape.cells().to_vec(),
+                interpreter.tape.pointer(),
+                interpreter.output,
+            )
+        });
+        assert_eq!(results[0], results[1]);
+    };
+
+    run(
+        include_str!("../../brainfuck-example/hello-world.bf"),
+        InterpreterBuilder::new(),
+    );
+    run(
+        include_str!("../../brainfuck-example/fizzbuzz.bf"),
+        InterpreterBuilder::new(),
+    );
+    let depth = 100_000;
+    run(
+        &format!("+{}{}.", "[-".repeat(depth), "]".repeat(depth)),
+        InterpreterBuilder::new(),
+    );
+
+    let programs = [
+        ",[>++++[<-------->-]<.,]",
+        "+[<]<+",
+        ">>>>>>>>>>>>>>>>>>+[-<+>]<.",
+        "-[>+>++<<-]>[>+<-]>.<<<<<++",
+    ];
+    for program in programs {
+        for width in [CellWidth::Bits8, CellWidth::Bits16, CellWidth::Bits32] {
+            for edge in [Edge::Error, Edge::Wrap, Edge::Grow] {
+                let builder = InterpreterBuilder::new()
+                    .eof(EofBehavior::Zero)
+                    .tape_size(std::num::NonZeroUsize::new(16).unwrap())
+                    .left_edge(edge)
+                    .right_edge(edge)
+                    .cell_width(width);
+                run(program, builder);
+            }
+        }
+    }
+}
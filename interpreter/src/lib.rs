@@ -1,91 +1,423 @@
-use error_messages::print_error;
-use owo_colors::OwoColorize;
 use std::fs;
-use std::io::{stdout, Read, Write};
+use std::io::{Read, Write};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 
 use lexer::{lex, Span};
-use parser::{Instruction, Parser, ParserError};
+pub use parser::ir::Passes;
+use parser::ir::{self, Node, Op};
+use parser::{Instruction, Parser, SpanTable};
 
-pub struct Interpreter<W> {
-    cells: [u8; 32_000],
-    pointer: usize,
+pub use crate::cell::{CellType, CellWidth, Overflow};
+pub use crate::debugger::{Debugger, Status};
+use crate::error::Trap;
+pub use crate::error::{RuntimeError, RuntimeResult};
+use crate::io::read_input;
+pub use crate::io::EofBehavior;
+pub use crate::tape::{Edge, Tape};
+use crate::vm::Bytecode;
+
+pub mod codegen;
+
+mod cell;
+mod debugger;
+mod error;
+mod io;
+#[cfg(feature = "jit")]
+mod jit;
+mod tape;
+mod vm;
+
+pub const DEFAULT_TAPE_SIZE: NonZeroUsize = NonZeroUsize::new(32_000).unwrap();
+
+/// How an [`Interpreter`] executes the optimised program.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum Backend {
+    /// Walk the tree of nodes, recursing into every loop.
+    TreeWalker,
+    /// Compile to flat bytecode with resolved jump targets and run it in a loop.
+    /// This is the default.
+    #[default]
+    Bytecode,
+    /// Compile to native x86-64 machine code. Falls back to the bytecode VM on
+    /// other targets and for cells that don't wrap around on overflow.
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+pub struct Interpreter<R, W> {
+    tape: Tape,
+    cell_type: CellType,
+    eof: EofBehavior,
+    passes: Passes,
+    backend: Backend,
+    input: R,
     output: W,
 }
 
-impl<W: Write> Interpreter<W> {
-    pub fn new(output: W) -> Self
-    where
-        W: Write,
-    {
+/// Configures an [`Interpreter`].
+///
+/// By default the tape has [`DEFAULT_TAPE_SIZE`] unsigned 8-bit wrapping cells,
+/// moving past either edge is an error and `,` leaves the cell unchanged at the
+/// end of input. Every optimisation pass is enabled and programs run on the
+/// bytecode VM.
+#[derive(Debug, Clone)]
+pub struct InterpreterBuilder {
+    tape_size: NonZeroUsize,
+    left_edge: Edge,
+    right_edge: Edge,
+    cell_type: CellType,
+    eof: EofBehavior,
+    passes: Passes,
+    backend: Backend,
+}
+
+impl Default for InterpreterBuilder {
+    fn default() -> Self {
+        InterpreterBuilder {
+            tape_size: DEFAULT_TAPE_SIZE,
+            left_edge: Edge::Error,
+            right_edge: Edge::Error,
+            cell_type: CellType::default(),
+            eof: EofBehavior::default(),
+            passes: Passes::default(),
+            backend: Backend::default(),
+        }
+    }
+}
+
+impl InterpreterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The initial number of cells.
+    pub fn tape_size(mut self, size: NonZeroUsize) -> Self {
+        self.tape_size = size;
+        self
+    }
+
+    /// What happens when `<` is executed on the first cell.
+    pub fn left_edge(mut self, edge: Edge) -> Self {
+        self.left_edge = edge;
+        self
+    }
+
+    /// What happens when `>` is executed on the last cell.
+    pub fn right_edge(mut self, edge: Edge) -> Self {
+        self.right_edge = edge;
+        self
+    }
+
+    pub fn cell_width(mut self, width: CellWidth) -> Self {
+        self.cell_type.width = width;
+        self
+    }
+
+    /// Whether cells hold signed values. Only matters for [`Overflow::Saturate`]
+    /// and [`Overflow::Error`], as wrapping is the same either way.
+    pub fn signed(mut self, signed: bool) -> Self {
+        self.cell_type.signed = signed;
+        self
+    }
+
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.cell_type.overflow = overflow;
+        self
+    }
+
+    pub fn eof(mut self, eof: EofBehavior) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    /// The optimisation passes run before a program is interpreted. The loop
+    /// passes are skipped unless cells wrap around on overflow.
+    pub fn passes(mut self, passes: Passes) -> Self {
+        self.passes = passes;
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn build<R: Read, W: Write>(self, input: R, output: W) -> Interpreter<R, W> {
         Interpreter {
-            cells: [0; 32_000],
-            pointer: 0,
+            tape: Tape::new(self.tape_size, self.left_edge, self.right_edge),
+            cell_type: self.cell_type,
+            eof: self.eof,
+            passes: self.passes,
+            backend: self.backend,
+            input,
             output,
         }
     }
+}
 
-    pub fn interpret(&mut self, instructions: &[Instruction]) {
-        for instruction in instructions.iter() {
-            match instruction {
-                Instruction::Left => self.pointer -= 1,
-                Instruction::Loop(loop_instructions) => self.interpret_loop(loop_instructions),
-                Instruction::Add => {
-                    self.cells[self.pointer] = self.cells[self.pointer].wrapping_add(1)
-                }
-                Instruction::Subtract => {
-                    self.cells[self.pointer] = self.cells[self.pointer].wrapping_sub(1)
-                }
-                Instruction::Right => self.pointer += 1 as usize,
-                Instruction::Output => {
-                    let char = self.cells[self.pointer] as char;
-                    write!(self.output, "{char}").unwrap();
-                }
-                Instruction::Input => self.cells[self.pointer] = read_input(),
-            }
-        }
+impl<R: Read, W: Write> Interpreter<R, W> {
+    /// Creates an interpreter with the default settings of [`InterpreterBuilder`]
+    /// that reads `,` from `input` and writes `.` to `output`.
+    pub fn new(input: R, output: W) -> Self {
+        InterpreterBuilder::new().build(input, output)
+    }
+
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+
+    /// Replaces the tape, like with a copy of it from earlier.
+    pub fn set_tape(&mut self, tape: Tape) {
+        self.tape = tape;
     }
 
-    fn interpret_loop(&mut self, instructions: &[Instruction]) {
-        while self.cells[self.pointer] != 0 {
-            self.interpret(instructions)
+    pub fn cell_type(&self) -> CellType {
+        self.cell_type
+    }
+
+    pub fn passes(&self) -> Passes {
+        self.passes
+    }
+
+    /// Optimises the instructions with the configured passes and runs them
+    /// against the current tape.
+    ///
+    /// Without a [`SpanTable`], the span of a runtime error is the position of
+    /// the instruction in the program, with both brackets of a loop counted.
+    pub fn interpret(&mut self, instructions: &[Instruction]) -> RuntimeResult<()> {
+        self.interpret_spanned(instructions, &SpanTable::default())
+    }
+
+    /// Like [`interpret`](Self::interpret), but runtime errors point to the
+    /// spans of the instructions in `spans`.
+    pub fn interpret_spanned(
+        &mut self,
+        instructions: &[Instruction],
+        spans: &SpanTable,
+    ) -> RuntimeResult<()> {
+        let mut passes = self.passes;
+        if self.cell_type.overflow != Overflow::Wrap {
+            passes.clear_loops = false;
+            passes.multiply_loops = false;
         }
+
+        let nodes = ir::lower(instructions, passes);
+        let result = match self.backend {
+            Backend::TreeWalker => self.walk(&nodes),
+            Backend::Bytecode => self.execute(&Bytecode::compile(&nodes)),
+            #[cfg(feature = "jit")]
+            Backend::Jit => jit::run(self, &nodes),
+        };
+        result.map_err(|(span, trap)| trap.at(spans.source_span(span).unwrap_or(span)))
     }
 
-    pub fn interpret_file(&mut self, file: &str) {
-        let file = fs::read_to_string(file);
+    /// Lexes, parses and runs `source`. Runtime errors point into `source`.
+    pub fn interpret_source(&mut self, source: &str) -> RuntimeResult<()> {
+        let (instructions, spans) = Parser::new(lex(source)).parse_with_spans()?;
+        self.interpret_spanned(&instructions, &spans)
+    }
 
-        match file {
-            Ok(file) => {
-                let instructions = Parser::new(lex(&file)).parse();
+    pub fn interpret_file(&mut self, file: &str) -> RuntimeResult<()> {
+        let source = fs::read_to_string(file)
+            .map_err(|err| RuntimeError::FileRead(PathBuf::from(file), err))?;
+        self.interpret_source(&source)
+    }
 
-                match instructions {
-                    Ok(instructions) => self.interpret(&instructions),
-                    Err(err) => {
-                        print_error(&err, &file);
-                        std::process::exit(1);
+    fn walk(&mut self, nodes: &[Node]) -> Result<(), (Span, Trap)> {
+        for node in nodes {
+            let fault = |trap| (node.span, trap);
+            match &node.op {
+                Op::Add(amount) => self.add(*amount).map_err(fault)?,
+                Op::Move(amount) => self.move_by(*amount).map_err(fault)?,
+                Op::Loop(body) => {
+                    while self.tape.get() != 0 {
+                        self.walk(body)?
                     }
                 }
+                Op::Clear => self.tape.set(0),
+                Op::Scan(amount) => self.scan(*amount).map_err(fault)?,
+                Op::Multiply(targets) => self.multiply(targets).map_err(fault)?,
+                Op::Output => self.output().map_err(fault)?,
+                Op::Input => self.input().map_err(fault)?,
             }
-            Err(err) => {
-                eprintln!("Error while reading file: {}", err);
-                std::process::exit(1)
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, amount: i64) -> Result<(), Trap> {
+        let value = self.cell_type.add(self.tape.get(), amount)?;
+        self.tape.set(value);
+        Ok(())
+    }
+
+    fn move_by(&mut self, amount: isize) -> Result<(), Trap> {
+        if amount < 0 {
+            self.tape.move_left(amount.unsigned_abs())
+        } else {
+            self.tape.move_right(amount as usize)
+        }
+    }
+
+    fn scan(&mut self, amount: isize) -> Result<(), Trap> {
+        while self.tape.get() != 0 {
+            self.move_by(amount)?
+        }
+        Ok(())
+    }
+
+    fn multiply(&mut self, targets: &[(isize, i64)]) -> Result<(), Trap> {
+        let value = self.cell_type.value(self.tape.get());
+        if value != 0 {
+            for (offset, factor) in targets {
+                self.move_by(*offset)?;
+                self.add(value.wrapping_mul(*factor))?;
+                self.move_by(-offset)?;
             }
+            self.tape.set(0);
         }
+        Ok(())
+    }
+
+    fn output(&mut self) -> Result<(), Trap> {
+        self.output
+            .write_all(&[self.tape.get() as u8])
+            .map_err(Trap::Io)
     }
-}
 
-fn read_input() -> u8 {
-    let mut buf = [0; 1];
-    std::io::stdin().read_exact(&mut buf).unwrap();
-    buf[0]
+    fn input(&mut self) -> Result<(), Trap> {
+        let value = match read_input(&mut self.input).map_err(Trap::Io)? {
+            Some(byte) => byte as u32,
+            None => self.eof.apply(self.tape.get(), self.cell_type),
+        };
+        self.tape.set(value);
+        Ok(())
+    }
 }
 
 #[test]
 fn hello_world() {
     let mut out = Vec::new();
-    Interpreter::new(out).interpret(&parser::hello_world()[..]);
+    Interpreter::new(std::io::empty(), &mut out)
+        .interpret(&parser::hello_world()[..])
+        .unwrap();
     assert_eq!(
         out.iter().map(|x| *x as char).collect::<String>(),
         "Hello World!\n"
     );
 }
+
+#[test]
+fn runtime_errors() {
+    let mut interpreter = Interpreter::new(std::io::empty(), std::io::sink());
+    assert!(matches!(
+        interpreter.interpret_source("+>\n[-]<<"),
+        Err(RuntimeError::PointerUnderflow(Span { from: 6, to: 8 }))
+    ));
+    assert!(matches!(
+        Interpreter::new(std::io::empty(), std::io::sink())
+            .interpret(&[Instruction::Add, Instruction::Left]),
+        Err(RuntimeError::PointerUnderflow(Span { from: 1, to: 2 }))
+    ));
+    assert!(matches!(
+        Interpreter::new(std::io::empty(), std::io::sink()).interpret_source("+[>+]"),
+        Err(RuntimeError::PointerOverflow(Span { from: 2, to: 3 }))
+    ));
+    assert!(matches!(
+        Interpreter::new(std::io::empty(), std::io::sink()).interpret_source("+]"),
+        Err(RuntimeError::Parser(errors))
+            if errors == [parser::ParserError::UnexpectedClosing(Span { from: 1, to: 2 })]
+    ));
+    assert!(matches!(
+        Interpreter::new(std::io::empty(), std::io::sink()).interpret_file("does-not-exist.bf"),
+        Err(RuntimeError::FileRead(..))
+    ));
+}
+
+#[test]
+fn tape_edges() {
+    let mut interpreter = InterpreterBuilder::new()
+        .tape_size(NonZeroUsize::new(4).unwrap())
+        .left_edge(Edge::Wrap)
+        .right_edge(Edge::Wrap)
+        .build(std::io::empty(), std::io::sink());
+    interpreter.interpret_source("<+>>+").unwrap();
+    assert_eq!(interpreter.tape().cells(), &[0, 1, 0, 1]);
+
+    let mut interpreter = InterpreterBuilder::new()
+        .tape_size(NonZeroUsize::MIN)
+        .right_edge(Edge::Grow)
+        .build(std::io::empty(), std::io::sink());
+    interpreter.interpret_source("++++[>++<-]>").unwrap();
+    assert_eq!(interpreter.tape().cells(), &[0, 8]);
+    assert_eq!(interpreter.tape().pointer(), 1);
+}
+
+#[test]
+fn cell_types() {
+    let mut interpreter = InterpreterBuilder::new()
+        .cell_width(CellWidth::Bits16)
+        .build(std::io::empty(), Vec::new());
+    interpreter.interpret_source("-.").unwrap();
+    assert_eq!(interpreter.tape().cells()[0], 0xffff);
+    assert_eq!(interpreter.output, [0xff]);
+
+    let mut interpreter = InterpreterBuilder::new()
+        .signed(true)
+        .overflow(Overflow::Error)
+        .build(std::io::empty(), std::io::sink());
+    assert!(interpreter.interpret_source("-").is_ok());
+    assert!(matches!(
+        interpreter.interpret_source("[+]+[+]"),
+        Err(RuntimeError::CellOverflow(Span { from: 5, to: 6 }))
+    ));
+}
+
+#[test]
+fn input() {
+    let mut out = Vec::new();
+    InterpreterBuilder::new()
+        .eof(EofBehavior::Zero)
+        .build("brainfuck".as_bytes(), &mut out)
+        .interpret_source(include_str!("../../brainfuck-example/uc.bf"))
+        .unwrap();
+    assert_eq!(out, b"BRAINFUCK");
+
+    let mut out = Vec::new();
+    Interpreter::new("ab".as_bytes(), &mut out)
+        .interpret_source(",.,.,.")
+        .unwrap();
+    assert_eq!(out, b"abb");
+}
+
+#[test]
+fn passes() {
+    let program = "++++++[>++++++++<-]>+[[>+>++<<-]>[<+>-]<-]>[-]>.";
+    let mut outputs = Vec::new();
+    for passes in [Passes::default(), Passes::none()] {
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let mut interpreter = InterpreterBuilder::new()
+                .passes(passes)
+                .backend(backend)
+                .build(std::io::empty(), Vec::new());
+            interpreter.interpret_source(program).unwrap();
+            outputs.push((interpreter.output, interpreter.tape.cells()[..4].to_vec()));
+        }
+    }
+    assert!(outputs.iter().all(|output| *output == outputs[0]));
+}
+
+#[test]
+fn deep_nesting() {
+    let depth = 100_000;
+    let source = format!("+{}->+<{}>.", "[".repeat(depth), "]".repeat(depth));
+    let (instructions, spans) = Parser::new(lex(&source)).parse_with_spans().unwrap();
+    assert_eq!(spans.len(), 2 * depth + 7);
+    assert_eq!(ir::lower(&instructions, Passes::default()).len(), 4);
+
+    let mut out = Vec::new();
+    Interpreter::new(std::io::empty(), &mut out)
+        .interpret_spanned(&instructions, &spans)
+        .unwrap();
+    assert_eq!(out, [1]);
+}
@@ -0,0 +1,127 @@
+use std::num::NonZeroUsize;
+
+use crate::error::Trap;
+
+/// What happens when the pointer moves past an edge of the tape.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum Edge {
+    /// Stop the program with a pointer underflow or overflow error.
+    #[default]
+    Error,
+    /// Continue at the opposite end of the tape, which makes it circular.
+    Wrap,
+    /// Add zeroed cells on this side of the tape.
+    Grow,
+}
+
+/// The cells of an [`Interpreter`](crate::Interpreter) and the pointer into them.
+///
+/// Cells hold the raw bits of their value, see [`CellType`](crate::CellType).
+#[derive(Debug, Clone)]
+pub struct Tape {
+    cells: Vec<u32>,
+    pointer: usize,
+    left: Edge,
+    right: Edge,
+}
+
+impl Tape {
+    /// Creates a zeroed tape of `size` cells with the pointer on the first cell.
+    pub fn new(size: NonZeroUsize, left: Edge, right: Edge) -> Self {
+        Tape {
+            cells: vec![0; size.get()],
+            pointer: 0,
+            left,
+            right,
+        }
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn cells(&self) -> &[u32] {
+        &self.cells
+    }
+
+    pub fn get(&self) -> u32 {
+        self.cells[self.pointer]
+    }
+
+    pub fn set(&mut self, value: u32) {
+        self.cells[self.pointer] = value;
+    }
+
+    #[cfg(feature = "jit")]
+    pub(crate) fn set_pointer(&mut self, pointer: usize) {
+        self.pointer = pointer;
+    }
+
+    #[cfg(feature = "jit")]
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u32 {
+        self.cells.as_mut_ptr()
+    }
+
+    pub(crate) fn move_right(&mut self, amount: usize) -> Result<(), Trap> {
+        let len = self.cells.len();
+        match self.pointer.checked_add(amount) {
+            Some(pointer) if pointer < len => self.pointer = pointer,
+            _ => match self.right {
+                Edge::Error => return Err(Trap::PointerOverflow),
+                Edge::Wrap => self.pointer = (self.pointer + amount % len) % len,
+                Edge::Grow => {
+                    let pointer = self
+                        .pointer
+                        .checked_add(amount)
+                        .ok_or(Trap::PointerOverflow)?;
+                    self.cells.resize(pointer + 1, 0);
+                    self.pointer = pointer;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    pub(crate) fn move_left(&mut self, amount: usize) -> Result<(), Trap> {
+        let len = self.cells.len();
+        match self.pointer.checked_sub(amount) {
+            Some(pointer) => self.pointer = pointer,
+            None => match self.left {
+                Edge::Error => return Err(Trap::PointerUnderflow),
+                Edge::Wrap => self.pointer = (self.pointer + len - amount % len) % len,
+                Edge::Grow => {
+                    // Grow by at least the current length so that walking left
+                    // doesn't shift the whole tape on every step.
+                    let extra = (amount - self.pointer).max(len);
+                    self.cells.splice(0..0, std::iter::repeat_n(0, extra));
+                    self.pointer = self.pointer + extra - amount;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn edges() {
+    let size = |size| NonZeroUsize::new(size).unwrap();
+    let mut tape = Tape::new(size(3), Edge::Error, Edge::Error);
+    assert!(matches!(tape.move_left(1), Err(Trap::PointerUnderflow)));
+    assert!(tape.move_right(2).is_ok());
+    assert!(matches!(tape.move_right(1), Err(Trap::PointerOverflow)));
+
+    let mut tape = Tape::new(size(3), Edge::Wrap, Edge::Wrap);
+    tape.move_left(1).unwrap();
+    assert_eq!(tape.pointer(), 2);
+    tape.move_right(5).unwrap();
+    assert_eq!(tape.pointer(), 1);
+
+    let mut tape = Tape::new(size(2), Edge::Grow, Edge::Grow);
+    tape.set(7);
+    tape.move_right(3).unwrap();
+    assert_eq!(tape.cells().len(), 4);
+    tape.move_left(5).unwrap();
+    assert_eq!(tape.get(), 0);
+    tape.move_right(2).unwrap();
+    assert_eq!(tape.get(), 7);
+}
@@ -0,0 +1,132 @@
+use std::io::{Read, Write};
+
+use lexer::Span;
+use parser::ir::{Node, Op};
+
+use crate::error::Trap;
+use crate::Interpreter;
+
+/// A flat instruction of the bytecode VM. Jumps hold the index of the
+/// instruction to continue at.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub(crate) enum Code {
+    Add(i64),
+    Move(isize),
+    Clear,
+    Scan(isize),
+    /// The range of the targets in [`Bytecode::targets`].
+    Multiply(usize, usize),
+    Input,
+    Output,
+    /// Jumps past the matching [`Code::JumpIfNotZero`] if the cell is zero.
+    JumpIfZero(usize),
+    /// Jumps back past the matching [`Code::JumpIfZero`] if the cell isn't zero.
+    JumpIfNotZero(usize),
+}
+
+/// A program compiled for the bytecode VM.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bytecode {
+    code: Vec<Code>,
+    spans: Vec<Span>,
+    targets: Vec<(isize, i64)>,
+}
+
+impl Bytecode {
+    pub(crate) fn compile(nodes: &[Node]) -> Self {
+        let mut bytecode = Bytecode::default();
+        // The rest of every block being compiled, and for loops the index and
+        // span of their `JumpIfZero`.
+        let mut blocks = vec![(nodes.iter(), None)];
+        while let Some((block, _)) = blocks.last_mut() {
+            let Some(node) = block.next() else {
+                if let Some((_, Some((open, span)))) = blocks.pop() {
+                    let close = bytecode.push(Code::JumpIfNotZero(open + 1), span);
+                    bytecode.code[open] = Code::JumpIfZero(close + 1);
+                }
+                continue;
+            };
+            let code = match &node.op {
+                Op::Add(amount) => Code::Add(*amount),
+                Op::Move(amount) => Code::Move(*amount),
+                Op::Clear => Code::Clear,
+                Op::Scan(amount) => Code::Scan(*amount),
+                Op::Multiply(targets) => {
+                    let start = bytecode.targets.len();
+                    bytecode.targets.extend_from_slice(targets);
+                    Code::Multiply(start, bytecode.targets.len())
+                }
+                Op::Input => Code::Input,
+                Op::Output => Code::Output,
+                Op::Loop(body) => {
+                    let open = bytecode.push(Code::JumpIfZero(0), node.span);
+                    blocks.push((body.iter(), Some((open, node.span))));
+                    continue;
+                }
+            };
+            bytecode.push(code, node.span);
+        }
+        bytecode
+    }
+
+    fn push(&mut self, code: Code, span: Span) -> usize {
+        self.code.push(code);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+}
+
+impl<R: Read, W: Write> Interpreter<R, W> {
+    pub(crate) fn execute(&mut self, bytecode: &Bytecode) -> Result<(), (Span, Trap)> {
+        let mut pc = 0;
+        while let Some(code) = bytecode.code.get(pc) {
+            let fault = |trap| (bytecode.spans[pc], trap);
+            match *code {
+                Code::Add(amount) => self.add(amount).map_err(fault)?,
+                Code::Move(amount) => self.move_by(amount).map_err(fault)?,
+                Code::Clear => self.tape.set(0),
+                Code::Scan(amount) => self.scan(amount).map_err(fault)?,
+                Code::Multiply(start, end) => self
+                    .multiply(&bytecode.targets[start..end])
+                    .map_err(fault)?,
+                Code::Input => self.input().map_err(fault)?,
+                Code::Output => self.output().map_err(fault)?,
+                Code::JumpIfZero(target) => {
+                    if self.tape.get() == 0 {
+                        pc = target;
+                        continue;
+                    }
+                }
+                Code::JumpIfNotZero(target) => {
+                    if self.tape.get() != 0 {
+                        pc = target;
+                        continue;
+                    }
+                }
+            }
+            pc += 1;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn compile_test() {
+    use parser::ir::{lower, Passes};
+    use parser::Parser;
+
+    let program = Parser::new(lexer::lex("+[>[-]<-]")).parse().unwrap();
+    let bytecode = Bytecode::compile(&lower(&program, Passes::default()));
+    assert_eq!(
+        bytecode.code,
+        vec![
+            Code::Add(1),
+            Code::JumpIfZero(7),
+            Code::Move(1),
+            Code::Clear,
+            Code::Move(-1),
+            Code::Add(-1),
+            Code::JumpIfNotZero(2),
+        ]
+    );
+}
@@ -0,0 +1,295 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use include_dir::{include_dir, Dir};
+use lexer::{LexerToken, Span, TokenType};
+use parser::Instruction;
+use serde::{Deserialize, Serialize};
+
+static LANG_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/langs");
+
+/// Language files in `langs` that their authors haven't defined yet, which
+/// aren't built in.
+const DRAFTS: [&str; 2] = ["de-ch", "uwu-owo"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Tokens {
+    left: String,
+    right: String,
+
+    #[serde(rename = "openLoop")]
+    open_loop: String,
+
+    #[serde(rename = "closeLoop")]
+    close_loop: String,
+    output: String,
+    input: String,
+    plus: String,
+    minus: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Settings {
+    #[serde(rename = "ignoreWhiteSpace")]
+    ignore_whitespace: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct LangFile {
+    tokens: Tokens,
+    settings: Settings,
+}
+
+/// A language that spells the eight brainfuck commands differently, read from
+/// a language file.
+///
+/// Text that doesn't spell a command is a comment. If the language ignores
+/// whitespace, there may be whitespace anywhere in a command.
+#[derive(Debug, Clone)]
+pub struct Dialect {
+    name: String,
+    commands: Vec<Command>,
+    ignore_whitespace: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Command {
+    token: TokenType,
+    /// How the command is written when rendering.
+    text: String,
+    /// What the lexer looks for, without whitespace if it is ignored.
+    pattern: String,
+}
+
+#[derive(Debug)]
+pub enum DialectError {
+    /// There is no built-in dialect with this name.
+    Unknown(String),
+    /// The language file couldn't be read.
+    Io(String, io::Error),
+    /// The language file isn't a valid dialect.
+    Invalid(String, String),
+}
+
+impl Dialect {
+    /// The built-in dialect `name`, or the language file at `name` if it ends
+    /// in `.json`.
+    pub fn load(name: &str) -> Result<Self, DialectError> {
+        let path = Path::new(name);
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            let json =
+                fs::read_to_string(path).map_err(|err| DialectError::Io(name.to_string(), err))?;
+            Self::from_json(name, &json)
+        } else {
+            Self::builtin(name)
+        }
+    }
+
+    pub fn builtin(name: &str) -> Result<Self, DialectError> {
+        let file = LANG_DIR
+            .get_file(format!("{name}.json"))
+            .filter(|_| !DRAFTS.contains(&name))
+            .ok_or_else(|| DialectError::Unknown(name.to_string()))?;
+        let json = file.contents_utf8().ok_or_else(|| {
+            DialectError::Invalid(name.to_string(), "the file isn't UTF-8".to_string())
+        })?;
+        Self::from_json(name, json)
+    }
+
+    pub fn builtin_names() -> Vec<&'static str> {
+        let mut names = LANG_DIR
+            .files()
+            .filter_map(|file| file.path().file_stem()?.to_str())
+            .filter(|name| !DRAFTS.contains(name))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    pub fn from_json(name: &str, json: &str) -> Result<Self, DialectError> {
+        let invalid = |message: String| DialectError::Invalid(name.to_string(), message);
+        let lang_file =
+            serde_json::from_str::<LangFile>(json).map_err(|err| invalid(err.to_string()))?;
+        let ignore_whitespace = lang_file.settings.ignore_whitespace;
+        let tokens = lang_file.tokens;
+        let commands = [
+            (TokenType::Left, tokens.left),
+            (TokenType::Right, tokens.right),
+            (TokenType::Add, tokens.plus),
+            (TokenType::Subtract, tokens.minus),
+            (TokenType::OpenLoop, tokens.open_loop),
+            (TokenType::CloseLoop, tokens.close_loop),
+            (TokenType::Output, tokens.output),
+            (TokenType::Input, tokens.input),
+        ]
+        .into_iter()
+        .map(|(token, text)| {
+            let pattern = if ignore_whitespace {
+                text.chars().filter(|c| !c.is_whitespace()).collect()
+            } else {
+                text.clone()
+            };
+            if pattern.is_empty() {
+                return Err(invalid(format!("the `{token}` command is empty")));
+            }
+            Ok(Command {
+                token,
+                text,
+                pattern,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Dialect {
+            name: name.to_string(),
+            commands,
+            ignore_whitespace,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The commands in `source`. Where more than one command matches, the
+    /// longest one wins.
+    pub fn lex(&self, source: &str) -> Vec<LexerToken> {
+        let mut tokens = Vec::new();
+        let mut start = 0;
+        while let Some(c) = source[start..].chars().next() {
+            if self.ignore_whitespace && c.is_whitespace() {
+                start += c.len_utf8();
+                continue;
+            }
+            let longest = self
+                .commands
+                .iter()
+                .filter_map(|command| Some((command, self.end_of(source, start, command)?)))
+                .max_by_key(|(command, _)| command.pattern.len());
+            match longest {
+                Some((command, end)) => {
+                    tokens.push(LexerToken::new(Span::from(start, end), command.token));
+                    start = end;
+                }
+                None => start += c.len_utf8(),
+            }
+        }
+        tokens
+    }
+
+    /// The end of `command` if it is spelled at `start`.
+    fn end_of(&self, source: &str, start: usize, command: &Command) -> Option<usize> {
+        let mut chars = source[start..]
+            .char_indices()
+            .filter(|(_, c)| !(self.ignore_whitespace && c.is_whitespace()));
+        let mut end = start;
+        for expected in command.pattern.chars() {
+            let (i, c) = chars.next()?;
+            if c != expected {
+                return None;
+            }
+            end = start + i + c.len_utf8();
+        }
+        Some(end)
+    }
+
+    /// The program in this dialect, one command per line with loop bodies
+    /// indented.
+    pub fn render(&self, instructions: &[Instruction]) -> String {
+        let mut out = String::new();
+        self.render_block(instructions, 0, &mut out);
+        out
+    }
+
+    fn render_block(&self, instructions: &[Instruction], depth: usize, out: &mut String) {
+        for instruction in instructions {
+            let token = match instruction {
+                Instruction::Loop(body) => {
+                    self.render_line(TokenType::OpenLoop, depth, out);
+                    self.render_block(body, depth + 1, out);
+                    TokenType::CloseLoop
+                }
+                Instruction::Add => TokenType::Add,
+                Instruction::Subtract => TokenType::Subtract,
+                Instruction::Left => TokenType::Left,
+                Instruction::Right => TokenType::Right,
+                Instruction::Input => TokenType::Input,
+                Instruction::Output => TokenType::Output,
+            };
+            self.render_line(token, depth, out);
+        }
+    }
+
+    fn render_line(&self, token: TokenType, depth: usize, out: &mut String) {
+        let command = self
+            .commands
+            .iter()
+            .find(|command| command.token == token)
+            .expect("every command has a spelling");
+        out.push_str(&"    ".repeat(depth));
+        out.push_str(&command.text);
+        out.push('\n');
+    }
+}
+
+impl fmt::Display for DialectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(
+                f,
+                "unknown dialect `{name}`, the built-in dialects are {}",
+                Dialect::builtin_names().join(", ")
+            ),
+            Self::Io(name, err) => write!(f, "couldn't read the dialect `{name}`: {err}"),
+            Self::Invalid(name, message) => write!(f, "invalid dialect `{name}`: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for DialectError {}
+
+#[test]
+fn dialects() {
+    use parser::Parser;
+
+    assert_eq!(Dialect::builtin_names(), ["c", "java"]);
+    for name in ["klingon", "de-ch"] {
+        assert!(matches!(
+            Dialect::builtin(name),
+            Err(DialectError::Unknown(_))
+        ));
+    }
+    assert!(matches!(
+        Dialect::from_json("broken", "{}"),
+        Err(DialectError::Invalid(..))
+    ));
+
+    let java = Dialect::builtin("java").unwrap();
+    let source = "cells[pointer] += 1; // one\nwhile (cells[pointer] > 0) {\n  cells[pointer]\n  -= 1;\ncells[pointer] -= 1;}";
+    let tokens = java.lex(source);
+    assert_eq!(
+        tokens.iter().map(|token| token.token).collect::<Vec<_>>(),
+        [
+            TokenType::Add,
+            TokenType::OpenLoop,
+            TokenType::Subtract,
+            TokenType::CloseLoop
+        ]
+    );
+    assert_eq!(tokens[2].span, Span::from(59, 81));
+
+    let program = Parser::new(lexer::lex("++[>+<-]>.,")).parse().unwrap();
+    for dialect in Dialect::builtin_names() {
+        let dialect = Dialect::builtin(dialect).unwrap();
+        let rendered = dialect.render(&program);
+        assert_eq!(
+            Parser::new(dialect.lex(&rendered)).parse(),
+            Ok(program.clone())
+        );
+    }
+}
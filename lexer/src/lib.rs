@@ -4,22 +4,69 @@ use std::fmt::Formatter;
 pub fn lex(input: &str) -> Vec<LexerToken> {
     input
         .char_indices()
-        .into_iter()
-        .filter_map(|(i, t)| match t {
-            '<' => Some((i, TokenType::Left)),
-            '>' => Some((i, TokenType::Right)),
-            '.' => Some((i, TokenType::Output)),
-            ',' => Some((i, TokenType::Input)),
-            '[' => Some((i, TokenType::OpenLoop)),
-            ']' => Some((i, TokenType::CloseLoop)),
-            '+' => Some((i, TokenType::Add)),
-            '-' => Some((i, TokenType::Subtract)),
-            _ => None,
-        })
+        .filter_map(|(i, c)| command(c).map(|t| (i, t)))
         .map(|(i, t)| LexerToken::new(Span::from(i, i + 1), t))
         .collect()
 }
 
+/// Like [`lex`], but keeps the text between commands, so the spans of the
+/// lexemes cover the whole input.
+///
+/// The text of a line between two commands is a comment, without the
+/// whitespace around it. All other text is whitespace.
+pub fn lex_lossless(input: &str) -> Vec<Lexeme> {
+    let mut lexemes = Vec::new();
+    let mut trivia_start = 0;
+    for (i, c) in input.char_indices() {
+        if let Some(token) = command(c) {
+            trivia(input, Span::from(trivia_start, i), &mut lexemes);
+            lexemes.push(Lexeme::Token(LexerToken::new(Span::from(i, i + 1), token)));
+            trivia_start = i + 1;
+        }
+    }
+    trivia(input, Span::from(trivia_start, input.len()), &mut lexemes);
+    lexemes
+}
+
+/// Splits the text between two commands into comments and whitespace.
+fn trivia(input: &str, span: Span, lexemes: &mut Vec<Lexeme>) {
+    let mut push = |lexeme: Lexeme| match (lexemes.last_mut(), lexeme) {
+        (Some(Lexeme::Whitespace(last)), Lexeme::Whitespace(next)) if last.to == next.from => {
+            last.to = next.to
+        }
+        (_, lexeme) if lexeme.span().from < lexeme.span().to => lexemes.push(lexeme),
+        _ => {}
+    };
+    let mut start = span.from;
+    for line in input[span.from..span.to].split_inclusive('\n') {
+        let end = start + line.len();
+        let comment_start = start + (line.len() - line.trim_start().len());
+        let comment_end = start + line.trim_end().len();
+        if comment_start < comment_end {
+            push(Lexeme::Whitespace(Span::from(start, comment_start)));
+            push(Lexeme::Comment(Span::from(comment_start, comment_end)));
+            push(Lexeme::Whitespace(Span::from(comment_end, end)));
+        } else {
+            push(Lexeme::Whitespace(Span::from(start, end)));
+        }
+        start = end;
+    }
+}
+
+fn command(c: char) -> Option<TokenType> {
+    match c {
+        '<' => Some(TokenType::Left),
+        '>' => Some(TokenType::Right),
+        '.' => Some(TokenType::Output),
+        ',' => Some(TokenType::Input),
+        '[' => Some(TokenType::OpenLoop),
+        ']' => Some(TokenType::CloseLoop),
+        '+' => Some(TokenType::Add),
+        '-' => Some(TokenType::Subtract),
+        _ => None,
+    }
+}
+
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum TokenType {
     Left,
@@ -50,6 +97,23 @@ pub struct Span {
     pub to: usize,
 }
 
+/// A command, or the text between commands.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Lexeme {
+    Token(LexerToken),
+    Comment(Span),
+    Whitespace(Span),
+}
+
+impl Lexeme {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Token(token) => token.span,
+            Self::Comment(span) | Self::Whitespace(span) => *span,
+        }
+    }
+}
+
 impl Span {
     pub fn from(from: usize, to: usize) -> Self {
         Span { from, to }
@@ -70,3 +134,31 @@ impl fmt::Display for TokenType {
         }
     }
 }
+
+#[test]
+fn lossless() {
+    let input = "add one +\r\n  \n[-] ok ";
+    let lexemes = lex_lossless(input);
+    assert_eq!(
+        lexemes[..3],
+        [
+            Lexeme::Comment(Span::from(0, 7)),
+            Lexeme::Whitespace(Span::from(7, 8)),
+            Lexeme::Token(LexerToken::new(Span::from(8, 9), TokenType::Add)),
+        ]
+    );
+    assert_eq!(lexemes[3], Lexeme::Whitespace(Span::from(9, 14)));
+    assert_eq!(
+        lexemes[7..],
+        [
+            Lexeme::Whitespace(Span::from(17, 18)),
+            Lexeme::Comment(Span::from(18, 20)),
+            Lexeme::Whitespace(Span::from(20, 21)),
+        ]
+    );
+    let text = lexemes
+        .iter()
+        .map(|lexeme| &input[lexeme.span().from..lexeme.span().to])
+        .collect::<String>();
+    assert_eq!(text, input);
+}
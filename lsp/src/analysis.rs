@@ -0,0 +1,436 @@
+use std::collections::BTreeMap;
+
+use error_messages::{Diagnostic as Message, Severity, SourceMap};
+use lexer::{lex, Span, TokenType};
+use parser::cst::{LoopNode, SyntaxNode, SyntaxTree};
+use parser::lint::{lint, Lints};
+use parser::Parser;
+use serde::{Deserialize, Serialize};
+
+/// A position in a document as the client counts it: lines and UTF-16 code
+/// units, both starting at 0.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    pub range: Range,
+    /// 1 for errors, 2 for warnings and 3 for notes.
+    pub severity: u8,
+    pub source: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub kind: u8,
+    pub range: Range,
+    pub selection_range: Range,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Hover {
+    pub contents: MarkupContent,
+    pub range: Range,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarkupContent {
+    pub kind: &'static str,
+    pub value: String,
+}
+
+/// A document the client has open.
+pub struct Document {
+    map: SourceMap<'static>,
+    tree: SyntaxTree,
+}
+
+impl Document {
+    pub fn new(source: String) -> Self {
+        let tree = SyntaxTree::parse(&source);
+        Document {
+            map: SourceMap::new(source),
+            tree,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        self.map.source()
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let source = self.source();
+        let line = self.map.line_index(offset);
+        let start = self.map.line_start(line);
+        let mut end = offset.min(source.len());
+        while !source.is_char_boundary(end) {
+            end -= 1;
+        }
+        Position {
+            line,
+            character: source[start..end].encode_utf16().count(),
+        }
+    }
+
+    /// The byte offset of `position`, which is clamped to the document.
+    pub fn offset(&self, position: Position) -> usize {
+        let line = position.line.min(self.map.line_count() - 1);
+        let start = self.map.line_start(line);
+        let mut units = 0;
+        for (i, c) in self.map.line(line).char_indices() {
+            if units >= position.character {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        start + self.map.line(line).len()
+    }
+
+    pub fn range(&self, span: Span) -> Range {
+        Range {
+            start: self.position(span.from),
+            end: self.position(span.to),
+        }
+    }
+
+    /// The bracket errors, or the lints if there are none.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let errors = self.tree.errors();
+        let messages = if errors.is_empty() {
+            let (instructions, spans) = Parser::new(lex(self.source()))
+                .parse_with_spans()
+                .expect("the syntax tree has no errors");
+            lint(&instructions, &spans, Lints::default())
+                .iter()
+                .map(Message::from)
+                .collect::<Vec<_>>()
+        } else {
+            errors.iter().map(Message::from).collect()
+        };
+        messages
+            .iter()
+            .filter_map(|message| {
+                let mut text = message.message.clone();
+                for note in &message.notes {
+                    text.push_str(&format!("\nnote: {note}"));
+                }
+                if let Some(help) = &message.help {
+                    text.push_str(&format!("\nhelp: {help}"));
+                }
+                Some(Diagnostic {
+                    range: self.range(message.span()?),
+                    severity: match message.severity {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                        Severity::Note => 3,
+                    },
+                    source: "bf",
+                    message: text,
+                })
+            })
+            .collect()
+    }
+
+    /// Both brackets of the loop with a bracket at `offset` or right before it.
+    pub fn matching_brackets(&self, offset: usize) -> Option<(Span, Option<Span>)> {
+        let on = |offset: usize| {
+            move |node: &LoopNode| {
+                node.open.from == offset || node.close.is_some_and(|close| close.from == offset)
+            }
+        };
+        find_loop(&self.tree.nodes, &on(offset))
+            .or_else(|| find_loop(&self.tree.nodes, &on(offset.checked_sub(1)?)))
+            .map(|node| (node.open, node.close))
+    }
+
+    /// The top level loops, named after the first comment in them.
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        self.tree
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                SyntaxNode::Loop(node) => Some(node),
+                _ => None,
+            })
+            .map(|node| {
+                let name = node
+                    .body
+                    .iter()
+                    .find_map(|node| match node {
+                        SyntaxNode::Comment(comment) => Some(comment.text.clone()),
+                        _ => None,
+                    })
+                    .unwrap_or_else(|| {
+                        format!("loop at line {}", self.position(node.open.from).line + 1)
+                    });
+                DocumentSymbol {
+                    name,
+                    // A function, the closest kind to a loop.
+                    kind: 12,
+                    range: self.range(node.span()),
+                    selection_range: self.range(node.open),
+                }
+            })
+            .collect()
+    }
+
+    /// The net effect of the commands in `selection`. For an empty selection,
+    /// the effect of the loop with a bracket there, or of the commands between
+    /// the loops around it.
+    pub fn hover(&self, selection: Span) -> Option<Hover> {
+        let offset = selection.from;
+        if selection.to > offset {
+            let commands = selected(&self.tree.nodes, selection)?;
+            return Some(self.commands_hover(commands));
+        }
+        if let Some((open, _)) = self.matching_brackets(offset) {
+            let node = find_loop(&self.tree.nodes, &|node: &LoopNode| node.open == open)?;
+            let effect = effect(&node.body);
+            return Some(Hover {
+                contents: MarkupContent {
+                    kind: "markdown",
+                    value: format!("**Every iteration of the loop**\n\n{}", effect.describe()),
+                },
+                range: self.range(node.span()),
+            });
+        }
+
+        let commands = commands_at(&self.tree.nodes, offset)?;
+        Some(self.commands_hover(commands))
+    }
+
+    /// The hover for `commands`, which isn't empty.
+    fn commands_hover(&self, commands: &[SyntaxNode]) -> Hover {
+        let span = Span::from(
+            commands[0].span().from,
+            commands[commands.len() - 1].span().to,
+        );
+        Hover {
+            contents: MarkupContent {
+                kind: "markdown",
+                value: format!("**These commands**\n\n{}", effect(commands).describe()),
+            },
+            range: self.range(span),
+        }
+    }
+}
+
+fn find_loop<'a>(
+    nodes: &'a [SyntaxNode],
+    found: &dyn Fn(&LoopNode) -> bool,
+) -> Option<&'a LoopNode> {
+    let mut blocks = vec![nodes.iter()];
+    while let Some(block) = blocks.last_mut() {
+        match block.next() {
+            Some(SyntaxNode::Loop(node)) if found(node) => return Some(node),
+            Some(SyntaxNode::Loop(node)) => blocks.push(node.body.iter()),
+            Some(_) => {}
+            None => {
+                blocks.pop();
+            }
+        }
+    }
+    None
+}
+
+/// The commands around the command at `offset` up to the next loop or bracket
+/// on either side.
+fn commands_at(mut nodes: &[SyntaxNode], offset: usize) -> Option<&[SyntaxNode]> {
+    let index = loop {
+        let index = nodes
+            .iter()
+            .position(|node| node.span().from <= offset && offset < node.span().to)?;
+        match &nodes[index] {
+            SyntaxNode::Loop(node) => nodes = &node.body,
+            SyntaxNode::Command(_) => break index,
+            _ => return None,
+        }
+    };
+    let is_command = |node: &SyntaxNode| matches!(node, SyntaxNode::Command(_));
+    let boundary = |node: &SyntaxNode| !is_command(node) && !node.is_trivia();
+    let start = nodes[..index]
+        .iter()
+        .rposition(boundary)
+        .map_or(0, |i| i + 1);
+    let end = nodes[index..]
+        .iter()
+        .position(boundary)
+        .map_or(nodes.len(), |i| index + i);
+    let start = start + nodes[start..end].iter().position(is_command)?;
+    let end = start + nodes[start..end].iter().rposition(is_command)? + 1;
+    Some(&nodes[start..end])
+}
+
+/// The commands and loops `selection` overlaps in the innermost loop body that
+/// contains all of it.
+fn selected(mut nodes: &[SyntaxNode], selection: Span) -> Option<&[SyntaxNode]> {
+    let overlaps = |node: &SyntaxNode| {
+        !node.is_trivia() && node.span().from < selection.to && selection.from < node.span().to
+    };
+    loop {
+        let start = nodes.iter().position(overlaps)?;
+        let end = nodes.iter().rposition(overlaps)? + 1;
+        match &nodes[start..end] {
+            [SyntaxNode::Loop(node)]
+                if node.open.to <= selection.from
+                    && node.close.is_none_or(|close| selection.to <= close.from) =>
+            {
+                nodes = &node.body;
+            }
+            selected => return Some(selected),
+        }
+    }
+}
+
+/// What a piece of straight-line code does to the tape.
+struct Effect {
+    pointer: isize,
+    /// The change of every cell by its offset from where the pointer started.
+    cells: BTreeMap<isize, i64>,
+    /// Whether there are loops or input in the code, so it does more.
+    partial: bool,
+}
+
+fn effect(nodes: &[SyntaxNode]) -> Effect {
+    let mut effect = Effect {
+        pointer: 0,
+        cells: BTreeMap::new(),
+        partial: false,
+    };
+    for node in nodes {
+        match node {
+            SyntaxNode::Command(token) => match token.token {
+                TokenType::Add => *effect.cells.entry(effect.pointer).or_default() += 1,
+                TokenType::Subtract => *effect.cells.entry(effect.pointer).or_default() -= 1,
+                TokenType::Right => effect.pointer += 1,
+                TokenType::Left => effect.pointer -= 1,
+                TokenType::Input => effect.partial = true,
+                _ => {}
+            },
+            SyntaxNode::Loop(_) => effect.partial = true,
+            _ => {}
+        }
+    }
+    effect.cells.retain(|_, change| *change != 0);
+    effect
+}
+
+impl Effect {
+    fn describe(&self) -> String {
+        let mut text = format!("Moves the pointer by {:+}", self.pointer);
+        if self.cells.is_empty() {
+            text.push_str(" and changes no cells");
+        } else {
+            let cells = self
+                .cells
+                .iter()
+                .map(|(offset, change)| format!("`{offset:+}` by {change:+}"))
+                .collect::<Vec<_>>();
+            text.push_str(&format!(" and changes the cells at {}", cells.join(", ")));
+        }
+        text.push('.');
+        if self.partial {
+            text.push_str(" This doesn't include inner loops and input.");
+        }
+        text
+    }
+}
+
+#[test]
+fn positions() {
+    let document = Document::new("+ größe\r\n𝄞 ->".to_string());
+    let offset = document.source().find('-').unwrap();
+    assert_eq!(
+        document.position(offset),
+        Position {
+            line: 1,
+            character: 3
+        }
+    );
+    assert_eq!(document.offset(document.position(offset)), offset);
+    assert_eq!(
+        document.offset(Position {
+            line: 0,
+            character: 100
+        }),
+        document.source().find('\r').unwrap()
+    );
+}
+
+#[test]
+fn features() {
+    let document = Document::new("+[ count\n  ->+<]\n>>[-]<".to_string());
+    assert_eq!(
+        document.matching_brackets(15),
+        Some((Span::from(1, 2), Some(Span::from(15, 16))))
+    );
+    assert_eq!(
+        document.matching_brackets(16),
+        document.matching_brackets(15)
+    );
+    assert_eq!(document.matching_brackets(5), None);
+
+    let symbols = document.symbols();
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[0].name, "count");
+    assert_eq!(symbols[1].name, "loop at line 3");
+
+    let hover = document.hover(Span::from(1, 1)).unwrap();
+    assert_eq!(
+        hover.contents.value,
+        "**Every iteration of the loop**\n\nMoves the pointer by +0 and changes the cells at `+0` by -1, `+1` by +1."
+    );
+    let hover = document.hover(Span::from(17, 17)).unwrap();
+    assert_eq!(hover.range, document.range(Span::from(17, 19)));
+    assert_eq!(
+        hover.contents.value,
+        "**These commands**\n\nMoves the pointer by +2 and changes no cells."
+    );
+    let hover = document.hover(Span::from(10, 13)).unwrap();
+    assert_eq!(hover.range, document.range(Span::from(11, 13)));
+    assert_eq!(
+        hover.contents.value,
+        "**These commands**\n\nMoves the pointer by +1 and changes the cells at `+0` by -1."
+    );
+    let hover = document.hover(Span::from(18, 23)).unwrap();
+    assert_eq!(hover.range, document.range(Span::from(18, 23)));
+    assert_eq!(
+        hover.contents.value,
+        "**These commands**\n\nMoves the pointer by +0 and changes no cells. This doesn't include inner loops and input."
+    );
+    assert!(document.hover(Span::from(2, 9)).is_none());
+
+    assert!(document.diagnostics().is_empty());
+    let document = Document::new("[]]".to_string());
+    let diagnostics = document.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, 1);
+}
+
+#[test]
+fn deep_nesting() {
+    let depth = 100_000;
+    let source = format!("{}+>{}", "[".repeat(depth), "]".repeat(depth));
+    let document = Document::new(source);
+    assert_eq!(
+        document.matching_brackets(depth - 1),
+        Some((
+            Span::from(depth - 1, depth),
+            Some(Span::from(depth + 2, depth + 3))
+        ))
+    );
+    let hover = document.hover(Span::from(depth + 1, depth + 1)).unwrap();
+    assert_eq!(hover.range, document.range(Span::from(depth, depth + 2)));
+}
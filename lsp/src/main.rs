@@ -0,0 +1,68 @@
+use std::io::{self, stdin, stdout, BufRead, Write};
+use std::process;
+
+use serde_json::Value;
+
+use crate::server::Server;
+
+mod analysis;
+mod server;
+
+/// A language server for brainfuck that speaks over stdin and stdout.
+fn main() {
+    let stdin = stdin();
+    let mut input = stdin.lock();
+    let mut server = Server::default();
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("Error while reading a message: {}", err);
+                continue;
+            }
+        };
+        if message["method"] == "exit" {
+            break;
+        }
+        for reply in server.handle(&message) {
+            if let Err(err) = write_message(&mut stdout().lock(), &reply) {
+                eprintln!("Error while writing a message: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+    process::exit(if server.is_shut_down() { 0 } else { 1 })
+}
+
+/// Reads the next message, or `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
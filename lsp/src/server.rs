@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use lexer::Span;
+use parser::format::{self, Options};
+use serde_json::{json, Value};
+
+use crate::analysis::{Document, Position, Range};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Answers the messages of a client. Documents are synced in full.
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shut_down: bool,
+}
+
+impl Server {
+    /// Whether the client asked the server to shut down before exiting.
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down
+    }
+
+    /// Handles a request or notification and returns the messages to send back.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "documentHighlightProvider": true,
+                    "documentSymbolProvider": true,
+                    "documentFormattingProvider": true,
+                    "hoverProvider": true,
+                },
+                "serverInfo": { "name": "bf-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/documentHighlight" => self.with_position(params, |document, offset| {
+                let highlights = match document.matching_brackets(offset) {
+                    Some((open, close)) => std::iter::once(open)
+                        .chain(close)
+                        .map(|span| json!({ "range": document.range(span) }))
+                        .collect(),
+                    None => Vec::new(),
+                };
+                json!(highlights)
+            }),
+            "textDocument/hover" => self.with_selection(params, |document, selection| {
+                json!(document.hover(selection))
+            }),
+            "textDocument/documentSymbol" => self
+                .document(params)
+                .map(|document| json!(document.symbols())),
+            "textDocument/formatting" => self.document(params).map(|document| {
+                let options = Options {
+                    indent: params["options"]["tabSize"]
+                        .as_u64()
+                        .map_or(Options::default().indent, |size| size as usize),
+                    ..Options::default()
+                };
+                match format::format(document.source(), &options) {
+                    Ok(formatted) if formatted != document.source() => json!([{
+                        "range": document.range(Span::from(0, document.source().len())),
+                        "newText": formatted,
+                    }]),
+                    Ok(_) => json!([]),
+                    // Programs with bracket errors can't be formatted.
+                    Err(_) => Value::Null,
+                }
+            }),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+        };
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        vec![response]
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let source = match method {
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+            "textDocument/didChange" => params["contentChanges"]
+                .as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change["text"].as_str()),
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, json!([]))];
+            }
+            _ => None,
+        };
+        match source {
+            Some(source) => {
+                let document = Document::new(source.to_string());
+                let diagnostics = json!(document.diagnostics());
+                self.documents.insert(uri.to_string(), document);
+                vec![publish_diagnostics(uri, diagnostics)]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn document(&self, params: &Value) -> Result<&Document, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        self.documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("`{uri}` is not open")))
+    }
+
+    fn with_position(
+        &self,
+        params: &Value,
+        answer: impl FnOnce(&Document, usize) -> Value,
+    ) -> Result<Value, (i64, String)> {
+        let document = self.document(params)?;
+        let position = serde_json::from_value::<Position>(params["position"].clone())
+            .map_err(|err| (INVALID_PARAMS, err.to_string()))?;
+        Ok(answer(document, document.offset(position)))
+    }
+
+    /// Like [`Self::with_position`], but with the selection in `range` if the
+    /// client sends one, and an empty selection at the position otherwise.
+    fn with_selection(
+        &self,
+        params: &Value,
+        answer: impl FnOnce(&Document, Span) -> Value,
+    ) -> Result<Value, (i64, String)> {
+        if params["range"].is_null() {
+            return self.with_position(params, |document, offset| {
+                answer(document, Span::from(offset, offset))
+            });
+        }
+        let document = self.document(params)?;
+        let range = serde_json::from_value::<Range>(params["range"].clone())
+            .map_err(|err| (INVALID_PARAMS, err.to_string()))?;
+        let start = document.offset(range.start);
+        let end = document.offset(range.end);
+        Ok(answer(document, Span::from(start.min(end), start.max(end))))
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+#[test]
+fn session() {
+    let mut server = Server::default();
+    let response =
+        server.handle(&json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }));
+    assert_eq!(response[0]["result"]["capabilities"]["hoverProvider"], true);
+
+    let uri = "file:///hello.bf";
+    let sent = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": { "textDocument": { "uri": uri, "languageId": "brainfuck", "version": 1, "text": "+[-]]" } },
+    }));
+    assert_eq!(
+        sent[0]["params"]["diagnostics"][0]["range"]["start"]["character"],
+        4
+    );
+
+    let sent = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didChange",
+        "params": {
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": "+[-]\n+  [>+<-]" }],
+        },
+    }));
+    assert_eq!(sent[0]["params"]["diagnostics"], json!([]));
+
+    let response = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "textDocument/documentHighlight",
+        "params": { "textDocument": { "uri": uri }, "position": { "line": 1, "character": 8 } },
+    }));
+    assert_eq!(
+        response[0]["result"][1]["range"]["start"],
+        json!({ "line": 1, "character": 8 })
+    );
+
+    let response = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "textDocument/hover",
+        "params": {
+            "textDocument": { "uri": uri },
+            "position": { "line": 1, "character": 4 },
+            "range": { "start": { "line": 1, "character": 4 }, "end": { "line": 1, "character": 6 } },
+        },
+    }));
+    assert_eq!(
+        response[0]["result"]["range"]["end"],
+        json!({ "line": 1, "character": 6 })
+    );
+
+    let response = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 4,
+        "method": "textDocument/formatting",
+        "params": { "textDocument": { "uri": uri }, "options": { "tabSize": 2, "insertSpaces": true } },
+    }));
+    assert_eq!(response[0]["result"][0]["newText"], "+ [-] + [>+<-]\n");
+
+    let response = server.handle(&json!({ "jsonrpc": "2.0", "id": 5, "method": "unknown" }));
+    assert_eq!(response[0]["error"]["code"], METHOD_NOT_FOUND);
+    server.handle(&json!({ "jsonrpc": "2.0", "id": 6, "method": "shutdown" }));
+    assert!(server.is_shut_down());
+}
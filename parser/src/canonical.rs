@@ -0,0 +1,142 @@
+use crate::Instruction;
+
+/// Whether a loop can never run because of where it is. `previous` is the
+/// instruction right before the loop in its block, `top_level` is whether the
+/// block is the program itself.
+///
+/// A loop is dead right after another loop, which only ends on a zero cell,
+/// and at the start of the program, where every cell is zero.
+pub fn is_dead_loop(previous: Option<&Instruction>, top_level: bool) -> bool {
+    match previous {
+        Some(Instruction::Loop(_)) => true,
+        Some(_) => false,
+        None => top_level,
+    }
+}
+
+/// Whether two commands right after each other do nothing.
+pub fn cancels(first: &Instruction, second: &Instruction) -> bool {
+    matches!(
+        (first, second),
+        (Instruction::Add, Instruction::Subtract)
+            | (Instruction::Subtract, Instruction::Add)
+            | (Instruction::Left, Instruction::Right)
+            | (Instruction::Right, Instruction::Left)
+    )
+}
+
+/// What [`canonicalise`] may assume about how the program is run.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct Assumptions {
+    /// Cells wrap around, so `+-` and `-+` do nothing even where a cell would
+    /// overflow in between.
+    pub wrapping_cells: bool,
+    /// The tape has no edges, so `<>` and `><` do nothing even where the
+    /// pointer would move off the tape in between.
+    pub unbounded_tape: bool,
+}
+
+impl Assumptions {
+    /// Whether a command cancelled by the one next to it can be removed.
+    fn removes(&self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::Add | Instruction::Subtract => self.wrapping_cells,
+            Instruction::Left | Instruction::Right => self.unbounded_tape,
+            _ => false,
+        }
+    }
+}
+
+/// Removes loops that can never run and, as far as `assumptions` allow, `+-`,
+/// `-+`, `<>` and `><`, until there is nothing left to remove. The program
+/// behaves the same wherever the assumptions hold.
+pub fn canonicalise(instructions: &[Instruction], assumptions: &Assumptions) -> Vec<Instruction> {
+    // The blocks being canonicalised, innermost last, with what is left of
+    // them and what they became so far.
+    let mut blocks = vec![(instructions.iter(), Vec::with_capacity(instructions.len()))];
+    loop {
+        let top_level = blocks.len() == 1;
+        let (rest, block) = blocks.last_mut().expect("the program is a block");
+        let Some(instruction) = rest.next() else {
+            let (_, block) = blocks.pop().expect("the program is a block");
+            match blocks.last_mut() {
+                Some((_, outer)) => outer.push(Instruction::Loop(block)),
+                None => return block,
+            }
+            continue;
+        };
+        match instruction {
+            Instruction::Loop(_) if is_dead_loop(block.last(), top_level) => {}
+            Instruction::Loop(body) => blocks.push((body.iter(), Vec::with_capacity(body.len()))),
+            _ if assumptions.removes(instruction)
+                && block.last().is_some_and(|last| cancels(last, instruction)) =>
+            {
+                block.pop();
+            }
+            _ => block.push(instruction.clone()),
+        }
+    }
+}
+
+/// The program as source with nothing but commands.
+pub fn to_source(instructions: &[Instruction]) -> String {
+    let mut source = String::new();
+    let mut blocks = vec![instructions.iter()];
+    while let Some(block) = blocks.last_mut() {
+        let Some(instruction) = block.next() else {
+            blocks.pop();
+            if !blocks.is_empty() {
+                source.push(']');
+            }
+            continue;
+        };
+        let command = match instruction {
+            Instruction::Loop(body) => {
+                blocks.push(body.iter());
+                '['
+            }
+            Instruction::Add => '+',
+            Instruction::Subtract => '-',
+            Instruction::Left => '<',
+            Instruction::Right => '>',
+            Instruction::Input => ',',
+            Instruction::Output => '.',
+        };
+        source.push(command);
+    }
+    source
+}
+
+#[test]
+fn canonicalise_test() {
+    use crate::Parser;
+    use lexer::lex;
+
+    let canonical = |source: &str, assumptions: Assumptions| {
+        to_source(&canonicalise(
+            &Parser::new(lex(source)).parse().unwrap(),
+            &assumptions,
+        ))
+    };
+    let both = Assumptions {
+        wrapping_cells: true,
+        unbounded_tape: true,
+    };
+    assert_eq!(canonical("[a comment] +- [another] ++", both), "++");
+    assert_eq!(canonical("+[->+<-+][-]<>[.]>+<>-<.", both), "+[->+<].");
+    assert_eq!(canonical("+[+-><]", both), "+[]");
+    assert_eq!(canonical(",[><]+", both), ",[]+");
+
+    let source = "[-]+-<>><[.]";
+    assert_eq!(canonical(source, Assumptions::default()), "+-<>><[.]");
+    let wrapping_cells = Assumptions {
+        wrapping_cells: true,
+        ..Assumptions::default()
+    };
+    assert_eq!(canonical(source, wrapping_cells), "<>><[.]");
+
+    let depth = 100_000;
+    let source = format!("+{}+-{}[-]", "[".repeat(depth), "]".repeat(depth));
+    let nested = format!("+{}{}", "[".repeat(depth), "]".repeat(depth));
+    assert_eq!(canonical(&source, both), nested);
+}
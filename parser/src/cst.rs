@@ -0,0 +1,284 @@
+use std::fmt;
+
+use lexer::{lex_lossless, Lexeme, LexerToken, Span, TokenType};
+
+use crate::{Instruction, ParserError, ParserResult};
+
+/// A program with everything in its source, comments and whitespace included.
+///
+/// Unlike the [`Parser`](crate::Parser), building the tree never fails: a `]`
+/// without a `[` becomes a [`SyntaxNode::StrayClose`] and a loop that is never
+/// closed has no `close`. Printing the tree gives back the source, with the
+/// changes made to the nodes.
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct SyntaxTree {
+    pub nodes: Vec<SyntaxNode>,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum SyntaxNode {
+    /// A command other than a bracket.
+    Command(LexerToken),
+    Loop(LoopNode),
+    /// A `]` without a `[`.
+    StrayClose(Span),
+    Comment(Trivia),
+    Whitespace(Trivia),
+}
+
+/// Text between the commands.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Trivia {
+    pub span: Span,
+    pub text: String,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct LoopNode {
+    /// The span of the `[`.
+    pub open: Span,
+    pub body: Vec<SyntaxNode>,
+    /// The span of the `]`, or `None` if the file ends first.
+    pub close: Option<Span>,
+}
+
+impl SyntaxTree {
+    /// Builds the tree without recursing into loops, so any depth of nesting
+    /// fits on the stack.
+    pub fn parse(source: &str) -> Self {
+        let trivia = |span: Span| Trivia {
+            span,
+            text: source[span.from..span.to].to_string(),
+        };
+        let mut nodes = Vec::new();
+        // The span of the `[` of every open loop and the nodes around it.
+        let mut open_loops: Vec<(Span, Vec<SyntaxNode>)> = Vec::new();
+        for lexeme in lex_lossless(source) {
+            let node = match lexeme {
+                Lexeme::Token(LexerToken {
+                    token: TokenType::CloseLoop,
+                    span,
+                }) => match open_loops.pop() {
+                    Some((open, outer)) => SyntaxNode::Loop(LoopNode {
+                        open,
+                        body: std::mem::replace(&mut nodes, outer),
+                        close: Some(span),
+                    }),
+                    None => SyntaxNode::StrayClose(span),
+                },
+                Lexeme::Token(LexerToken {
+                    token: TokenType::OpenLoop,
+                    span,
+                }) => {
+                    open_loops.push((span, std::mem::take(&mut nodes)));
+                    continue;
+                }
+                Lexeme::Token(token) => SyntaxNode::Command(token),
+                Lexeme::Comment(span) => SyntaxNode::Comment(trivia(span)),
+                Lexeme::Whitespace(span) => SyntaxNode::Whitespace(trivia(span)),
+            };
+            nodes.push(node);
+        }
+        while let Some((open, outer)) = open_loops.pop() {
+            let body = std::mem::replace(&mut nodes, outer);
+            nodes.push(SyntaxNode::Loop(LoopNode {
+                open,
+                body,
+                close: None,
+            }));
+        }
+        SyntaxTree { nodes }
+    }
+
+    /// The errors the [`Parser`](crate::Parser) finds in the program.
+    pub fn errors(&self) -> Vec<ParserError> {
+        let mut errors = Vec::new();
+        let mut blocks = vec![self.nodes.iter()];
+        while let Some(block) = blocks.last_mut() {
+            match block.next() {
+                Some(SyntaxNode::Loop(node)) => {
+                    if node.close.is_none() {
+                        errors.push(ParserError::UnexpectedEOF(node.open));
+                    }
+                    blocks.push(node.body.iter());
+                }
+                Some(SyntaxNode::StrayClose(span)) => {
+                    errors.push(ParserError::UnexpectedClosing(*span))
+                }
+                Some(_) => {}
+                None => {
+                    blocks.pop();
+                }
+            }
+        }
+        errors.sort_by_key(|err| err.span().from);
+        errors
+    }
+
+    /// The instructions of the program, without the comments.
+    pub fn instructions(&self) -> ParserResult<Vec<Instruction>> {
+        let errors = self.errors();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        // The rest of every block being translated and its instructions so far.
+        let mut blocks = vec![(self.nodes.iter(), Vec::new())];
+        loop {
+            let (block, instructions) = blocks.last_mut().expect("the program is never closed");
+            let instruction = match block.next() {
+                Some(SyntaxNode::Command(token)) => match token.token {
+                    TokenType::Add => Instruction::Add,
+                    TokenType::Subtract => Instruction::Subtract,
+                    TokenType::Left => Instruction::Left,
+                    TokenType::Right => Instruction::Right,
+                    TokenType::Input => Instruction::Input,
+                    TokenType::Output => Instruction::Output,
+                    TokenType::OpenLoop | TokenType::CloseLoop => {
+                        unreachable!("brackets are loops")
+                    }
+                },
+                Some(SyntaxNode::Loop(node)) => {
+                    blocks.push((node.body.iter(), Vec::new()));
+                    continue;
+                }
+                Some(_) => continue,
+                None => {
+                    let (_, body) = blocks.pop().expect("the block was just looked at");
+                    match blocks.last_mut() {
+                        Some((_, instructions)) => {
+                            instructions.push(Instruction::Loop(body));
+                            continue;
+                        }
+                        None => return Ok(body),
+                    }
+                }
+            };
+            instructions.push(instruction);
+        }
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_nodes(f, &self.nodes)
+    }
+}
+
+impl SyntaxNode {
+    /// The span of the node, for a loop from the `[` to the `]` or the end of
+    /// its body.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Command(token) => token.span,
+            Self::Loop(node) => node.span(),
+            Self::StrayClose(span) => *span,
+            Self::Comment(trivia) | Self::Whitespace(trivia) => trivia.span,
+        }
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Self::Comment(_) | Self::Whitespace(_))
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_nodes(f, std::slice::from_ref(self))
+    }
+}
+
+impl LoopNode {
+    pub fn span(&self) -> Span {
+        // A loop that isn't closed ends with its body, which can end with a
+        // loop that isn't closed either.
+        let mut node = self;
+        let to = loop {
+            match (node.close, node.body.last()) {
+                (Some(close), _) => break close.to,
+                (None, Some(SyntaxNode::Loop(last))) => node = last,
+                (None, Some(last)) => break last.span().to,
+                (None, None) => break node.open.to,
+            }
+        };
+        Span::from(self.open.from, to)
+    }
+}
+
+/// Drops the loops in a loop one after another, like the drop of
+/// [`Instruction`].
+impl Drop for LoopNode {
+    fn drop(&mut self) {
+        let mut nodes = std::mem::take(&mut self.body);
+        while let Some(mut node) = nodes.pop() {
+            if let SyntaxNode::Loop(node) = &mut node {
+                nodes.append(&mut node.body);
+            }
+        }
+    }
+}
+
+/// Writes the nodes as source, without recursing into loops.
+fn write_nodes(f: &mut fmt::Formatter<'_>, nodes: &[SyntaxNode]) -> fmt::Result {
+    // The rest of every block being written, and whether its loop is closed.
+    let mut blocks = vec![(nodes.iter(), false)];
+    while let Some((block, _)) = blocks.last_mut() {
+        match block.next() {
+            Some(SyntaxNode::Command(token)) => write!(f, "{}", token.token)?,
+            Some(SyntaxNode::Loop(node)) => {
+                f.write_str("[")?;
+                blocks.push((node.body.iter(), node.close.is_some()));
+            }
+            Some(SyntaxNode::StrayClose(_)) => f.write_str("]")?,
+            Some(SyntaxNode::Comment(trivia) | SyntaxNode::Whitespace(trivia)) => {
+                f.write_str(&trivia.text)?
+            }
+            None => {
+                if let Some((_, true)) = blocks.pop() {
+                    f.write_str("]")?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn syntax_tree() {
+    use crate::Parser;
+
+    let source = "add [ loop\n  -] + ";
+    let mut tree = SyntaxTree::parse(source);
+    assert_eq!(tree.to_string(), source);
+    assert_eq!(tree.nodes.len(), 6);
+    assert_eq!(tree.nodes[2].span(), Span::from(4, 15));
+    assert_eq!(tree.instructions(), Parser::new(lexer::lex(source)).parse());
+
+    // Editing the tree keeps the comments.
+    if let SyntaxNode::Loop(node) = &mut tree.nodes[2] {
+        node.body.push(SyntaxNode::Command(LexerToken::new(
+            Span::from(14, 14),
+            TokenType::Right,
+        )));
+    }
+    assert_eq!(tree.to_string(), "add [ loop\n  ->] + ");
+
+    let source = "] [[+]";
+    let tree = SyntaxTree::parse(source);
+    assert_eq!(tree.to_string(), source);
+    assert_eq!(
+        tree.errors(),
+        Parser::new(lexer::lex(source)).parse().unwrap_err()
+    );
+
+    let depth = 100_000;
+    let source = format!("{} a{}", "[".repeat(depth), "]".repeat(depth - 1));
+    let tree = SyntaxTree::parse(&source);
+    assert_eq!(tree.to_string(), source);
+    assert_eq!(tree.nodes[0].span(), Span::from(0, source.len()));
+    assert_eq!(
+        tree.errors(),
+        [ParserError::UnexpectedEOF(Span::from(0, 1))]
+    );
+    let tree = SyntaxTree::parse(&format!("{source}]"));
+    assert_eq!(tree.instructions().unwrap().len(), 1);
+}
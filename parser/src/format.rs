@@ -0,0 +1,375 @@
+use lexer::lex;
+
+use crate::canonical::{canonicalise, to_source, Assumptions};
+use crate::cst::{SyntaxNode, SyntaxTree};
+use crate::{Parser, ParserResult};
+
+/// How [`format`] lays out a program.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Options {
+    /// The number of columns a line is wrapped at. Comments and runs of a
+    /// command that are longer than that are not wrapped.
+    pub width: usize,
+    /// The number of spaces a loop body is indented by.
+    pub indent: usize,
+    /// Whether to keep the text between the commands.
+    pub comments: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            width: 80,
+            indent: 4,
+            comments: true,
+        }
+    }
+}
+
+/// Reformats a program.
+///
+/// Runs of the same command are grouped together and separated from other runs
+/// by a space. Loops without comments or other loops in them stay on one line
+/// if they fit, every other loop body goes on its own lines, indented by
+/// nesting depth. Comments are trimmed and go at the end of the line they
+/// were on, or on their own line if they were on one. A blank line in the
+/// source is kept, more than one are merged.
+///
+/// The program has to parse, so that the loops can be indented.
+pub fn format(source: &str, options: &Options) -> ParserResult<String> {
+    let tree = SyntaxTree::parse(source);
+    let errors = tree.errors();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let items = group(&tree.nodes);
+    let mut writer = Writer {
+        options,
+        out: String::new(),
+        line: String::new(),
+        line_depth: 0,
+        depth: 0,
+    };
+    writer.items(&items);
+    writer.flush();
+    let out = writer.out.trim_end_matches('\n');
+    Ok(if out.is_empty() {
+        String::new()
+    } else {
+        format!("{out}\n")
+    })
+}
+
+/// Shrinks a program to its commands, without the ones that do nothing under
+/// `assumptions`. See [`canonicalise`] for what is removed.
+pub fn minify(source: &str, assumptions: &Assumptions) -> ParserResult<String> {
+    let instructions = Parser::new(lex(source)).parse()?;
+    Ok(to_source(&canonicalise(&instructions, assumptions)))
+}
+
+/// The program as a flat list, with the body of a loop between its `Open` and
+/// `Close`.
+enum Item {
+    Run(char, usize),
+    Open,
+    Close,
+    /// The comments and whitespace between two commands.
+    Comment(String),
+}
+
+fn group(nodes: &[SyntaxNode]) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut trivia = String::new();
+    let mut blocks = vec![nodes.iter()];
+    while let Some(block) = blocks.last_mut() {
+        let Some(node) = block.next() else {
+            blocks.pop();
+            push_trivia(&mut items, &mut trivia);
+            if !blocks.is_empty() {
+                items.push(Item::Close);
+            }
+            continue;
+        };
+        let item = match node {
+            SyntaxNode::Comment(node) | SyntaxNode::Whitespace(node) => {
+                trivia.push_str(&node.text);
+                continue;
+            }
+            SyntaxNode::Loop(node) => {
+                push_trivia(&mut items, &mut trivia);
+                items.push(Item::Open);
+                blocks.push(node.body.iter());
+                continue;
+            }
+            SyntaxNode::Command(token) => {
+                Item::Run(token.token.to_string().chars().next().unwrap(), 1)
+            }
+            SyntaxNode::StrayClose(_) => continue,
+        };
+        push_trivia(&mut items, &mut trivia);
+        // Runs around whitespace without a blank line are merged.
+        if let (Some(Item::Run(last, count)), Item::Run(command, 1)) = (items.last_mut(), &item) {
+            if last == command {
+                *count += 1;
+                continue;
+            }
+        }
+        items.push(item);
+    }
+    push_trivia(&mut items, &mut trivia);
+    items
+}
+
+fn push_trivia(items: &mut Vec<Item>, trivia: &mut String) {
+    let text = std::mem::take(trivia);
+    // Whitespace only matters for blank lines.
+    if !text.trim().is_empty() || has_blank_line(&text) {
+        items.push(Item::Comment(text));
+    }
+}
+
+fn has_blank_line(text: &str) -> bool {
+    let lines = text.split('\n').collect::<Vec<_>>();
+    lines.len() > 2
+        && lines[1..lines.len() - 1]
+            .iter()
+            .any(|line| line.trim().is_empty())
+}
+
+struct Writer<'a> {
+    options: &'a Options,
+    out: String,
+    /// The line being written, without its indentation.
+    line: String,
+    /// The nesting depth the current line started at.
+    line_depth: usize,
+    depth: usize,
+}
+
+impl Writer<'_> {
+    fn items(&mut self, items: &[Item]) {
+        let mut i = 0;
+        while i < items.len() {
+            match &items[i] {
+                Item::Run(command, count) => {
+                    let mut count = *count;
+                    let available = self.available().max(1);
+                    while count > 0 {
+                        let chunk = count.min(available);
+                        self.word(&command.to_string().repeat(chunk));
+                        count -= chunk;
+                    }
+                }
+                Item::Open => match self.inline(&items[i + 1..]) {
+                    Some((text, size)) if text.len() <= self.available() => {
+                        self.word(&text);
+                        i += size + 1;
+                    }
+                    _ => {
+                        self.word("[");
+                        self.depth += 1;
+                    }
+                },
+                Item::Close => {
+                    self.depth -= 1;
+                    self.flush();
+                    self.line.push(']');
+                }
+                Item::Comment(text) => self.comment(text),
+            }
+            i += 1;
+        }
+    }
+
+    /// The columns left for code on a new line.
+    fn available(&self) -> usize {
+        self.options
+            .width
+            .saturating_sub(self.depth * self.options.indent)
+    }
+
+    /// Adds code to the current line, or to a new one if it doesn't fit. Code
+    /// never goes on the line of a `[`.
+    fn word(&mut self, word: &str) {
+        let width = self.line_depth * self.options.indent + self.line.len() + 1 + word.len();
+        if self.line.ends_with('[') || width > self.options.width {
+            self.flush();
+        }
+        if !self.line.is_empty() {
+            self.line.push(' ');
+        }
+        self.line.push_str(word);
+    }
+
+    fn comment(&mut self, text: &str) {
+        if !self.options.comments {
+            if has_blank_line(text) {
+                self.blank_line();
+            }
+            return;
+        }
+        let lines = text.split('\n').collect::<Vec<_>>();
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            if i > 0 {
+                self.flush();
+            }
+            if line.is_empty() {
+                if i > 0 && i < lines.len() - 1 {
+                    self.blank_line();
+                }
+            } else {
+                if !self.line.is_empty() {
+                    self.line.push(' ');
+                }
+                self.line.push_str(line);
+                // Code after a comment goes on a new line.
+                self.flush();
+            }
+        }
+    }
+
+    /// The loop whose body starts `items` on one line, if it only has commands
+    /// in it, and the number of items in its body.
+    fn inline(&self, items: &[Item]) -> Option<(String, usize)> {
+        let mut text = String::from("[");
+        for (size, item) in items.iter().enumerate() {
+            match item {
+                Item::Run(command, count) => text.push_str(&command.to_string().repeat(*count)),
+                Item::Comment(_) if !self.options.comments => {}
+                Item::Close => {
+                    text.push(']');
+                    return Some((text, size));
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    fn blank_line(&mut self) {
+        self.flush();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            let indent = self.line_depth * self.options.indent;
+            self.out.push_str(&" ".repeat(indent));
+            self.out.push_str(&self.line);
+            self.out.push('\n');
+            self.line.clear();
+        }
+        self.line_depth = self.depth;
+    }
+}
+
+#[test]
+fn format_test() {
+    let source = "\
+[ prints 2 ]  ++ [>+<-]
+
+set up the
+ loop
+>++++++++++++[ count down
+<+++> -] > .<.<<
+,[.,]";
+    let options = Options {
+        width: 16,
+        ..Options::default()
+    };
+    let formatted = format(source, &options).unwrap();
+    assert_eq!(
+        formatted,
+        "\
+[ prints 2
+] ++ [>+<-]
+
+set up the
+loop
+> ++++++++++++ [ count down
+    < +++ > -
+] > . < . << ,
+[.,]
+"
+    );
+    assert_eq!(format(&formatted, &options).unwrap(), formatted);
+
+    let stripped = format(
+        source,
+        &Options {
+            comments: false,
+            ..Options::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        stripped,
+        "[] ++ [>+<-]\n\n> ++++++++++++ [<+++>-] > . < . << , [.,]\n"
+    );
+    assert!(format("+]", &options).is_err());
+    assert_eq!(
+        minify(
+            source,
+            &Assumptions {
+                wrapping_cells: true,
+                unbounded_tape: true,
+            }
+        )
+        .unwrap(),
+        "++[>+<-]>++++++++++++[<+++>-]>.<.<<,[.,]"
+    );
+}
+
+#[test]
+fn idempotent() {
+    let sources = [
+        "",
+        "+++",
+        "a comment\n\n\n\nanother\n+",
+        "+[->+<]# the end",
+        "[[[-]>]<<] ,[.,] ++++++++++++++++++++++++++++++++++++++++",
+        "+[ outer\n  [ inner\n    -]\n>]\n\n<<<.",
+        ">>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>[++++++++++++++++++++++++++++++++++++++]",
+    ];
+    for width in [8, 16, 80] {
+        for comments in [true, false] {
+            let options = Options {
+                width,
+                comments,
+                ..Options::default()
+            };
+            for source in sources {
+                let formatted = format(source, &options).unwrap();
+                assert_eq!(
+                    format(&formatted, &options).unwrap(),
+                    formatted,
+                    "{source:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn deep_nesting() {
+    let depth = 100_000;
+    let source = format!(",{}-{}[]", "[".repeat(depth), "]".repeat(depth));
+    let options = Options {
+        indent: 0,
+        ..Options::default()
+    };
+    let formatted = format(&source, &options).unwrap();
+    assert_eq!(
+        formatted,
+        format!(
+            ", [\n{}[-]\n{}] []\n",
+            "[\n".repeat(depth - 2),
+            "]\n".repeat(depth - 2)
+        )
+    );
+    assert_eq!(format(&formatted, &options).unwrap(), formatted);
+}
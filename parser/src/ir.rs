@@ -0,0 +1,324 @@
+use lexer::Span;
+
+use crate::canonical::is_dead_loop;
+use crate::Instruction;
+
+/// An instruction of the optimised intermediate representation.
+///
+/// The span covers every instruction the node was built from. They are
+/// positions in the program, counted in pre-order with both brackets of a loop,
+/// which the [`SpanTable`](crate::SpanTable) of the program maps to the source.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Node {
+    pub op: Op,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Op {
+    /// Adds to the current cell. Negative amounts subtract.
+    Add(i64),
+    /// Moves the pointer. Negative amounts move to the left.
+    Move(isize),
+    Loop(Vec<Node>),
+    /// `[-]` or `[+]`, sets the current cell to zero.
+    Clear,
+    /// `[>]` or `[<<]`, moves by the amount until a zero cell is found.
+    Scan(isize),
+    /// `[->+>++<<]`, adds the current cell times the factor to the cell at each
+    /// offset and then sets the current cell to zero.
+    Multiply(Vec<(isize, i64)>),
+    Input,
+    Output,
+}
+
+/// Drops the loops in a loop one after another, like the drop of
+/// [`Instruction`].
+impl Drop for Op {
+    fn drop(&mut self) {
+        if let Self::Loop(body) = self {
+            let mut nodes = std::mem::take(body);
+            while let Some(mut node) = nodes.pop() {
+                if let Self::Loop(body) = &mut node.op {
+                    nodes.append(body);
+                }
+            }
+        }
+    }
+}
+
+/// The optimisations applied by [`lower`]. Every pass is enabled by default.
+///
+/// The loop passes assume that cells wrap around on overflow.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Passes {
+    /// Merge runs of `+`, `-`, `>` or `<` into a single [`Op::Add`] or [`Op::Move`].
+    pub run_length: bool,
+    /// Turn `[-]` and `[+]` into [`Op::Clear`].
+    pub clear_loops: bool,
+    /// Turn loops that only move the pointer into [`Op::Scan`].
+    pub scan_loops: bool,
+    /// Turn balanced loops that decrement the current cell once into [`Op::Multiply`].
+    pub multiply_loops: bool,
+    /// Leave out loops right after another loop, which can never run. Loops at
+    /// the start aren't left out, as the tape may be used by an earlier program.
+    pub dead_loops: bool,
+}
+
+impl Default for Passes {
+    fn default() -> Self {
+        Passes {
+            run_length: true,
+            clear_loops: true,
+            scan_loops: true,
+            multiply_loops: true,
+            dead_loops: true,
+        }
+    }
+}
+
+impl Passes {
+    /// Translates every instruction into its own node.
+    pub fn none() -> Self {
+        Passes {
+            run_length: false,
+            clear_loops: false,
+            scan_loops: false,
+            multiply_loops: false,
+            dead_loops: false,
+        }
+    }
+}
+
+/// Translates the instructions into the intermediate representation.
+pub fn lower(instructions: &[Instruction], passes: Passes) -> Vec<Node> {
+    // The blocks being lowered, from the program to the innermost loop.
+    let mut blocks = vec![Block {
+        instructions,
+        next: 0,
+        nodes: Vec::new(),
+        start: 0,
+    }];
+    let mut position = 0;
+    loop {
+        let block = blocks.last_mut().expect("the program is never closed");
+        let instructions = block.instructions;
+        let i = block.next;
+        let Some(instruction) = instructions.get(i) else {
+            let body = blocks.pop().expect("the block was just looked at");
+            let Some(outer) = blocks.last_mut() else {
+                return body.nodes;
+            };
+            position += 1;
+            let span = Span::from(body.start, position);
+            outer.nodes.push(lower_loop(body.nodes, span, passes));
+            continue;
+        };
+        block.next += 1;
+
+        let start = position;
+        if passes.dead_loops
+            && matches!(instruction, Instruction::Loop(_))
+            && is_dead_loop(i.checked_sub(1).map(|i| &instructions[i]), false)
+        {
+            position += instruction.size();
+            continue;
+        }
+        position += 1;
+        let op = match instruction {
+            Instruction::Add => Op::Add(1),
+            Instruction::Subtract => Op::Add(-1),
+            Instruction::Right => Op::Move(1),
+            Instruction::Left => Op::Move(-1),
+            Instruction::Input => Op::Input,
+            Instruction::Output => Op::Output,
+            Instruction::Loop(body) => {
+                blocks.push(Block {
+                    instructions: body,
+                    next: 0,
+                    nodes: Vec::new(),
+                    start,
+                });
+                continue;
+            }
+        };
+
+        // Only runs in the same direction are merged, so that a cell or the
+        // pointer still hits the same edges as it would step by step.
+        match (block.nodes.last_mut(), &op) {
+            (
+                Some(Node {
+                    op: Op::Add(amount),
+                    span,
+                }),
+                Op::Add(step),
+            ) if passes.run_length && amount.signum() == step.signum() => {
+                *amount += step;
+                span.to = position;
+            }
+            (
+                Some(Node {
+                    op: Op::Move(amount),
+                    span,
+                }),
+                Op::Move(step),
+            ) if passes.run_length && amount.signum() == step.signum() => {
+                *amount += step;
+                span.to = position;
+            }
+            _ => block.nodes.push(Node {
+                op,
+                span: Span::from(start, position),
+            }),
+        }
+    }
+}
+
+/// A program or loop body that [`lower`] is in the middle of.
+struct Block<'a> {
+    instructions: &'a [Instruction],
+    /// The index of the next instruction to lower.
+    next: usize,
+    nodes: Vec<Node>,
+    /// The position of the loop.
+    start: usize,
+}
+
+fn lower_loop(body: Vec<Node>, span: Span, passes: Passes) -> Node {
+    let op = match body.as_slice() {
+        [Node {
+            op: Op::Add(1 | -1),
+            ..
+        }] if passes.clear_loops => Op::Clear,
+        [Node {
+            op: Op::Move(amount),
+            ..
+        }] if passes.scan_loops => Op::Scan(*amount),
+        _ => match multiply_targets(&body) {
+            Some(targets) if passes.multiply_loops => Op::Multiply(targets),
+            _ => Op::Loop(body),
+        },
+    };
+    Node { op, span }
+}
+
+/// The factor for every offset if the loop body only adds and moves, returns to
+/// where it started and decrements the current cell by exactly one.
+fn multiply_targets(body: &[Node]) -> Option<Vec<(isize, i64)>> {
+    let mut offset = 0;
+    let mut targets: Vec<(isize, i64)> = Vec::new();
+    for node in body {
+        match node.op {
+            Op::Move(amount) => offset += amount,
+            Op::Add(amount) => match targets.iter_mut().find(|(o, _)| *o == offset) {
+                Some((_, factor)) => *factor += amount,
+                None => targets.push((offset, amount)),
+            },
+            _ => return None,
+        }
+    }
+
+    if offset != 0 {
+        return None;
+    }
+    match targets.iter().position(|(o, _)| *o == 0) {
+        Some(origin) if targets[origin].1 == -1 => {
+            targets.remove(origin);
+        }
+        _ => return None,
+    }
+    targets.retain(|(_, factor)| *factor != 0);
+    Some(targets)
+}
+
+#[test]
+fn lower_test() {
+    use crate::Parser;
+    use lexer::lex;
+
+    let program = Parser::new(lex("+++[->++>+<<]>>[-]<[<]>>-,."))
+        .parse()
+        .unwrap();
+    assert_eq!(
+        lower(&program, Passes::default()),
+        vec![
+            Node {
+                op: Op::Add(3),
+                span: Span::from(0, 3)
+            },
+            Node {
+                op: Op::Multiply(vec![(1, 2), (2, 1)]),
+                span: Span::from(3, 13)
+            },
+            Node {
+                op: Op::Move(2),
+                span: Span::from(13, 15)
+            },
+            Node {
+                op: Op::Clear,
+                span: Span::from(15, 18)
+            },
+            Node {
+                op: Op::Move(-1),
+                span: Span::from(18, 19)
+            },
+            Node {
+                op: Op::Scan(-1),
+                span: Span::from(19, 22)
+            },
+            Node {
+                op: Op::Move(2),
+                span: Span::from(22, 24)
+            },
+            Node {
+                op: Op::Add(-1),
+                span: Span::from(24, 25)
+            },
+            Node {
+                op: Op::Input,
+                span: Span::from(25, 26)
+            },
+            Node {
+                op: Op::Output,
+                span: Span::from(26, 27)
+            },
+        ]
+    );
+
+    let program = Parser::new(lex("+-[-]")).parse().unwrap();
+    assert_eq!(
+        lower(&program, Passes::none()),
+        vec![
+            Node {
+                op: Op::Add(1),
+                span: Span::from(0, 1)
+            },
+            Node {
+                op: Op::Add(-1),
+                span: Span::from(1, 2)
+            },
+            Node {
+                op: Op::Loop(vec![Node {
+                    op: Op::Add(-1),
+                    span: Span::from(3, 4)
+                }]),
+                span: Span::from(2, 5)
+            },
+        ]
+    );
+
+    let program = Parser::new(lex("+[-][dead]")).parse().unwrap();
+    assert_eq!(
+        lower(&program, Passes::default()),
+        vec![
+            Node {
+                op: Op::Add(1),
+                span: Span::from(0, 1)
+            },
+            Node {
+                op: Op::Clear,
+                span: Span::from(1, 4)
+            },
+        ]
+    );
+}
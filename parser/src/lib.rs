@@ -7,14 +7,31 @@ use peekmore::{PeekMore, PeekMoreIterator};
 use crate::ParserError::UnexpectedEOF;
 use lexer::{LexerToken, Span, TokenType};
 
-pub type ParserResult<T> = Result<T, ParserError>;
+pub mod canonical;
+pub mod cst;
+pub mod format;
+pub mod ir;
+pub mod lint;
+
+/// Parsing fails with every error found in the program.
+pub type ParserResult<T> = Result<T, Vec<ParserError>>;
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum ParserError {
+    /// A `[` that is never closed.
     UnexpectedEOF(Span),
+    /// A `]` without a `[`.
     UnexpectedClosing(Span),
 }
 
+impl ParserError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnexpectedEOF(span) | Self::UnexpectedClosing(span) => *span,
+        }
+    }
+}
+
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum Instruction {
     Loop(Vec<Instruction>),
@@ -26,103 +43,148 @@ pub enum Instruction {
     Output,
 }
 
+impl Instruction {
+    /// The number of positions the instruction takes in a [`SpanTable`].
+    pub fn size(&self) -> usize {
+        let mut size = 0;
+        let mut instructions = vec![self];
+        while let Some(instruction) = instructions.pop() {
+            size += match instruction {
+                Self::Loop(body) => {
+                    instructions.extend(body);
+                    2
+                }
+                _ => 1,
+            };
+        }
+        size
+    }
+}
+
+/// Drops the loops in a loop one after another, as dropping them recursively
+/// overflows the stack for deeply nested programs.
+impl Drop for Instruction {
+    fn drop(&mut self) {
+        if let Self::Loop(body) = self {
+            let mut instructions = std::mem::take(body);
+            while let Some(mut instruction) = instructions.pop() {
+                if let Self::Loop(body) = &mut instruction {
+                    instructions.append(body);
+                }
+            }
+        }
+    }
+}
+
+/// The source spans of parsed instructions.
+///
+/// Instructions are numbered in pre-order, and a loop takes two positions: the
+/// one of the loop itself holds the span of its `[` and the one after its body
+/// holds the span of its `]`. So a loop at position `p` is closed at position
+/// `p + size - 1`, see [`Instruction::size`]. The positions match the spans of
+/// the [`ir::Node`]s lowered from the instructions.
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct SpanTable {
+    spans: Vec<Span>,
+}
+
+impl SpanTable {
+    pub fn new(spans: Vec<Span>) -> Self {
+        SpanTable { spans }
+    }
+
+    pub fn get(&self, position: usize) -> Option<Span> {
+        self.spans.get(position).copied()
+    }
+
+    /// The span in the source from the first to the last of the `positions`,
+    /// like the span of an [`ir::Node`].
+    pub fn source_span(&self, positions: Span) -> Option<Span> {
+        let from = self.get(positions.from)?;
+        let to = self.get(positions.to.checked_sub(1)?)?;
+        Some(Span::from(from.from, to.to))
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+}
+
 pub struct Parser {
     tokens: PeekMoreIterator<IntoIter<LexerToken>>,
-    instructions: Vec<Instruction>,
+    spans: Vec<Span>,
+    errors: Vec<ParserError>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<LexerToken>) -> Self {
         Parser {
             tokens: tokens.into_iter().peekmore(),
-            instructions: Vec::new(),
+            spans: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
-    pub fn parse(mut self) -> ParserResult<Vec<Instruction>> {
-        while let Some(token) = self.next() {
-            let instruction = self.parse_token(&token);
-            self.instructions.push(instruction?);
-        }
-        Ok(self.instructions)
+    pub fn parse(self) -> ParserResult<Vec<Instruction>> {
+        self.parse_with_spans()
+            .map(|(instructions, _)| instructions)
     }
 
-    fn parse_token(&mut self, token: &LexerToken) -> ParserResult<Instruction> {
-        match token {
-            LexerToken {
-                token: TokenType::OpenLoop,
-                ..
-            } => self.parse_loop(token),
-            LexerToken {
-                token: TokenType::CloseLoop,
-                ..
-            } => Err(ParserError::UnexpectedClosing(token.span)),
-            LexerToken {
-                token: TokenType::Add,
-                ..
-            } => Ok(Instruction::Add),
-            LexerToken {
-                token: TokenType::Subtract,
-                ..
-            } => Ok(Instruction::Subtract),
-            LexerToken {
-                token: TokenType::Left,
-                ..
-            } => Ok(Instruction::Left),
-            LexerToken {
-                token: TokenType::Right,
-                ..
-            } => Ok(Instruction::Right),
-            LexerToken {
-                token: TokenType::Output,
-                ..
-            } => Ok(Instruction::Output),
-            LexerToken {
-                token: TokenType::Input,
-                ..
-            } => Ok(Instruction::Input),
-        }
-    }
-
-    fn parse_loop(&mut self, first_token: &LexerToken) -> ParserResult<Instruction> {
-        let mut loop_instructions = Vec::new();
+    /// Parses the program and keeps the span of every instruction.
+    ///
+    /// The parser skips unmatched `]` and closes unmatched `[` at the end of the
+    /// file, so that every bracket error of the program is reported, in the
+    /// order of the source. Loops are parsed without recursion, so any depth of
+    /// nesting fits on the stack.
+    pub fn parse_with_spans(mut self) -> ParserResult<(Vec<Instruction>, SpanTable)> {
+        let mut block = Vec::new();
+        // The span of the `[` of every open loop and the block around it.
+        let mut open_loops: Vec<(Span, Vec<Instruction>)> = Vec::new();
         while let Some(token) = self.next() {
-            match token {
-                LexerToken {
-                    token: TokenType::CloseLoop,
-                    ..
-                } => return Ok(Instruction::Loop(loop_instructions)),
-
-                token => {
-                    let new_token = self.parse_token(&token)?;
-                    loop_instructions.push(new_token);
+            let instruction = match token.token {
+                TokenType::CloseLoop => match open_loops.pop() {
+                    Some((_, outer)) => Instruction::Loop(std::mem::replace(&mut block, outer)),
+                    None => {
+                        self.errors.push(ParserError::UnexpectedClosing(token.span));
+                        continue;
+                    }
+                },
+                TokenType::OpenLoop => {
+                    self.spans.push(token.span);
+                    open_loops.push((token.span, std::mem::take(&mut block)));
+                    continue;
                 }
-            }
+                TokenType::Add => Instruction::Add,
+                TokenType::Subtract => Instruction::Subtract,
+                TokenType::Left => Instruction::Left,
+                TokenType::Right => Instruction::Right,
+                TokenType::Output => Instruction::Output,
+                TokenType::Input => Instruction::Input,
+            };
+            self.spans.push(token.span);
+            block.push(instruction);
+        }
+        while let Some((span, outer)) = open_loops.pop() {
+            self.errors.push(UnexpectedEOF(span));
+            let body = std::mem::replace(&mut block, outer);
+            block.push(Instruction::Loop(body));
         }
 
-        Err(UnexpectedEOF(Span::from(
-            first_token.span.from,
-            first_token.span.to,
-        )))
+        if self.errors.is_empty() {
+            Ok((block, SpanTable::new(self.spans)))
+        } else {
+            self.errors.sort_by_key(|err| err.span().from);
+            Err(self.errors)
+        }
     }
 
     fn next(&mut self) -> Option<LexerToken> {
         self.tokens.next()
     }
-
-    fn consume_elements(&mut self, amount: usize) {
-        for _ in 0..amount {
-            self.tokens.next();
-        }
-    }
-
-    fn peek(&mut self) -> Option<LexerToken> {
-        self.tokens.peek().copied()
-    }
-
-    fn peek_nth(&mut self, amount: usize) -> Option<LexerToken> {
-        self.tokens.peek_nth(amount).copied()
-    }
 }
 
 #[test]
@@ -252,22 +314,66 @@ fn parser_test() {
     )
 }
 
+#[test]
+fn errors() {
+    assert_eq!(
+        Parser::new(lexer::lex("[[+]]]+[-[[]>")).parse(),
+        Err(vec![
+            ParserError::UnexpectedClosing(Span::from(5, 6)),
+            ParserError::UnexpectedEOF(Span::from(7, 8)),
+            ParserError::UnexpectedEOF(Span::from(9, 10)),
+        ])
+    );
+}
+
+#[test]
+fn spans() {
+    let (instructions, spans) = Parser::new(lexer::lex("+ [-[>]\n]."))
+        .parse_with_spans()
+        .unwrap();
+    assert_eq!(spans.len(), 8);
+    assert_eq!(instructions[1].size(), 6);
+    assert_eq!(spans.get(1), Some(Span::from(2, 3)));
+    assert_eq!(spans.get(6), Some(Span::from(8, 9)));
+    assert_eq!(spans.get(7), Some(Span::from(9, 10)));
+    assert_eq!(spans.source_span(Span::from(3, 6)), Some(Span::from(4, 7)));
+    assert_eq!(spans.source_span(Span::from(7, 9)), None);
+}
+
 pub fn hello_world() -> Vec<Instruction> {
     vec![
+        Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
         Instruction::Add,
         Instruction::Loop(vec![
             Instruction::Right,
             Instruction::Add,
+            Instruction::Add,
+            Instruction::Add,
+            Instruction::Add,
             Instruction::Loop(vec![
                 Instruction::Right,
                 Instruction::Add,
+                Instruction::Add,
                 Instruction::Right,
                 Instruction::Add,
+                Instruction::Add,
+                Instruction::Add,
                 Instruction::Right,
                 Instruction::Add,
+                Instruction::Add,
+                Instruction::Add,
                 Instruction::Right,
                 Instruction::Add,
                 Instruction::Left,
+                Instruction::Left,
+                Instruction::Left,
+                Instruction::Left,
                 Instruction::Subtract,
             ]),
             Instruction::Right,
@@ -277,22 +383,35 @@ pub fn hello_world() -> Vec<Instruction> {
             Instruction::Right,
             Instruction::Subtract,
             Instruction::Right,
+            Instruction::Right,
             Instruction::Add,
             Instruction::Loop(vec![Instruction::Left]),
             Instruction::Left,
             Instruction::Subtract,
         ]),
         Instruction::Right,
+        Instruction::Right,
         Instruction::Output,
         Instruction::Right,
         Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
         Instruction::Output,
         Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
         Instruction::Output,
         Instruction::Output,
         Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
         Instruction::Output,
         Instruction::Right,
+        Instruction::Right,
         Instruction::Output,
         Instruction::Left,
         Instruction::Subtract,
@@ -300,16 +419,32 @@ pub fn hello_world() -> Vec<Instruction> {
         Instruction::Left,
         Instruction::Output,
         Instruction::Add,
+        Instruction::Add,
+        Instruction::Add,
         Instruction::Output,
         Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
         Instruction::Output,
         Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
+        Instruction::Subtract,
         Instruction::Output,
         Instruction::Right,
+        Instruction::Right,
         Instruction::Add,
         Instruction::Output,
         Instruction::Right,
         Instruction::Add,
+        Instruction::Add,
         Instruction::Output,
     ]
 }
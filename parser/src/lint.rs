@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use lexer::Span;
+
+use crate::canonical::cancels;
+use crate::{Instruction, SpanTable};
+
+/// Something in a program that is probably a mistake.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
+pub enum LintKind {
+    /// A loop that starts on a cell that is always zero, so its body never runs.
+    DeadLoop,
+    /// A loop that changes neither the pointer nor a cell, so it never ends once
+    /// it is entered.
+    InfiniteLoop,
+    /// `+-`, `-+`, `<>` or `><`, which do nothing.
+    CancellingPair,
+    /// Code after a loop that is always entered and never ends.
+    UnreachableCode,
+    /// A move in straight-line code that always takes the pointer below the
+    /// first cell.
+    NegativePointer,
+}
+
+impl LintKind {
+    pub const ALL: [LintKind; 5] = [
+        LintKind::DeadLoop,
+        LintKind::InfiniteLoop,
+        LintKind::CancellingPair,
+        LintKind::UnreachableCode,
+        LintKind::NegativePointer,
+    ];
+
+    /// The name used to allow the lint, like `dead-loop`.
+    pub fn name(self) -> &'static str {
+        match self {
+            LintKind::DeadLoop => "dead-loop",
+            LintKind::InfiniteLoop => "infinite-loop",
+            LintKind::CancellingPair => "cancelling-pair",
+            LintKind::UnreachableCode => "unreachable-code",
+            LintKind::NegativePointer => "negative-pointer",
+        }
+    }
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for LintKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        LintKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| format!("unknown lint `{name}`"))
+    }
+}
+
+/// A warning about a program.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Lint {
+    pub kind: LintKind,
+    /// The span in the source of what the lint is about.
+    pub span: Span,
+    /// The span in the source of what caused it, like the loop before a dead
+    /// loop or the infinite loop before unreachable code.
+    pub cause: Option<Span>,
+}
+
+/// The lints [`lint`] checks for. Every lint is enabled by default.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Lints {
+    pub dead_loops: bool,
+    pub infinite_loops: bool,
+    pub cancelling_pairs: bool,
+    pub unreachable_code: bool,
+    pub negative_pointer: bool,
+}
+
+impl Default for Lints {
+    fn default() -> Self {
+        Lints {
+            dead_loops: true,
+            infinite_loops: true,
+            cancelling_pairs: true,
+            unreachable_code: true,
+            negative_pointer: true,
+        }
+    }
+}
+
+impl Lints {
+    /// Disables the lint.
+    pub fn allow(mut self, kind: LintKind) -> Self {
+        *self.flag(kind) = false;
+        self
+    }
+
+    pub fn is_enabled(&self, kind: LintKind) -> bool {
+        match kind {
+            LintKind::DeadLoop => self.dead_loops,
+            LintKind::InfiniteLoop => self.infinite_loops,
+            LintKind::CancellingPair => self.cancelling_pairs,
+            LintKind::UnreachableCode => self.unreachable_code,
+            LintKind::NegativePointer => self.negative_pointer,
+        }
+    }
+
+    fn flag(&mut self, kind: LintKind) -> &mut bool {
+        match kind {
+            LintKind::DeadLoop => &mut self.dead_loops,
+            LintKind::InfiniteLoop => &mut self.infinite_loops,
+            LintKind::CancellingPair => &mut self.cancelling_pairs,
+            LintKind::UnreachableCode => &mut self.unreachable_code,
+            LintKind::NegativePointer => &mut self.negative_pointer,
+        }
+    }
+}
+
+/// Checks the program for the enabled lints, in the order of the source.
+///
+/// `spans` is the span table the parser returned with the instructions. The
+/// checks assume nothing about the cell width, so a cell is only known to be
+/// nonzero if it is for every width.
+pub fn lint(instructions: &[Instruction], spans: &SpanTable, lints: Lints) -> Vec<Lint> {
+    let mut linter = Linter {
+        spans,
+        lints,
+        loops: loops(instructions),
+        found: Vec::new(),
+    };
+    linter.program(instructions);
+    linter.found.sort_by_key(|lint| lint.span.from);
+    linter.found
+}
+
+/// What [`lint`] needs to know about a loop before checking it.
+struct Loop {
+    /// The number of positions the loop takes, see [`Instruction::size`].
+    size: usize,
+    /// How far the loop body moves the pointer, if it's the same every time.
+    net_move: Option<isize>,
+}
+
+/// Every loop of the program by its position, found in a single pass.
+fn loops(instructions: &[Instruction]) -> HashMap<usize, Loop> {
+    let mut loops = HashMap::new();
+    let mut position = 0;
+    // The rest of every block being walked, with the position of its loop and
+    // how far it moved the pointer so far.
+    let mut blocks = vec![(instructions.iter(), 0, Some(0))];
+    while let Some((block, _, offset)) = blocks.last_mut() {
+        match block.next() {
+            Some(Instruction::Loop(body)) => {
+                blocks.push((body.iter(), position, Some(0)));
+                position += 1;
+            }
+            Some(instruction) => {
+                match instruction {
+                    Instruction::Left => *offset = offset.map(|offset| offset - 1),
+                    Instruction::Right => *offset = offset.map(|offset| offset + 1),
+                    _ => {}
+                }
+                position += 1;
+            }
+            None => {
+                let (_, start, net_move) = blocks.pop().expect("the block was just looked at");
+                if let Some((_, _, offset)) = blocks.last_mut() {
+                    position += 1;
+                    let size = position - start;
+                    loops.insert(start, Loop { size, net_move });
+                    // An outer loop only moves by the same amount if this
+                    // one always ends where it started.
+                    if net_move != Some(0) {
+                        *offset = None;
+                    }
+                }
+            }
+        }
+    }
+    loops
+}
+
+/// A program or loop body that [`Linter::program`] is in the middle of.
+struct Block<'a> {
+    instructions: &'a [Instruction],
+    /// The index of the next instruction.
+    next: usize,
+    /// The position of the next instruction.
+    position: usize,
+    state: State,
+    /// The positions of the loop right before the next instruction.
+    previous_loop: Option<Span>,
+}
+
+/// What is known about the tape at a point of the program.
+struct State {
+    /// The position of the pointer, if it is known.
+    pointer: Option<isize>,
+    /// How far the pointer moved since the state was created.
+    base: isize,
+    /// The known values of cells by `base` plus their offset from the pointer.
+    /// `None` is a cell that can have any value.
+    cells: HashMap<isize, Option<i64>>,
+    /// Whether the cells missing from `cells` are zero.
+    zeroed: bool,
+}
+
+impl State {
+    /// Nothing is known about the cells, only where the pointer is.
+    fn unknown(pointer: Option<isize>) -> Self {
+        State {
+            pointer,
+            base: 0,
+            cells: HashMap::new(),
+            zeroed: false,
+        }
+    }
+
+    fn current(&self) -> Option<i64> {
+        match self.cells.get(&self.base) {
+            Some(value) => *value,
+            None if self.zeroed => Some(0),
+            None => None,
+        }
+    }
+
+    fn set_current(&mut self, value: Option<i64>) {
+        self.cells.insert(self.base, value);
+    }
+
+    fn move_by(&mut self, amount: isize) {
+        self.base += amount;
+        self.pointer = self.pointer.map(|pointer| pointer + amount);
+    }
+}
+
+struct Linter<'a> {
+    spans: &'a SpanTable,
+    lints: Lints,
+    loops: HashMap<usize, Loop>,
+    found: Vec<Lint>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, kind: LintKind, positions: Span, cause: Option<Span>) {
+        if !self.lints.is_enabled(kind) {
+            return;
+        }
+        if let Some(span) = self.spans.source_span(positions) {
+            let cause = cause.and_then(|cause| self.spans.source_span(cause));
+            self.found.push(Lint { kind, span, cause });
+        }
+    }
+
+    /// The number of positions of the instruction at `position`.
+    fn size(&self, instruction: &Instruction, position: usize) -> usize {
+        match instruction {
+            Instruction::Loop(_) => self.loops[&position].size,
+            _ => 1,
+        }
+    }
+
+    /// Checks the program one instruction after another, without recursing
+    /// into loops.
+    fn program(&mut self, instructions: &[Instruction]) {
+        self.cancelling_pairs(instructions, 0);
+        let mut blocks = vec![Block {
+            instructions,
+            next: 0,
+            position: 0,
+            state: State {
+                pointer: Some(0),
+                base: 0,
+                cells: HashMap::new(),
+                zeroed: true,
+            },
+            previous_loop: None,
+        }];
+        while let Some(block) = blocks.last_mut() {
+            let Some(instruction) = block.instructions.get(block.next) else {
+                blocks.pop();
+                if let Some(outer) = blocks.last_mut() {
+                    self.after_loop(outer);
+                }
+                continue;
+            };
+            block.next += 1;
+            let position = block.position;
+            let size = self.size(instruction, position);
+            let positions = Span::from(position, position + size);
+            let state = &mut block.state;
+            match instruction {
+                Instruction::Add => state.set_current(state.current().map(|v| v.wrapping_add(1))),
+                Instruction::Subtract => {
+                    state.set_current(state.current().map(|v| v.wrapping_sub(1)))
+                }
+                Instruction::Left | Instruction::Right => {
+                    state.move_by(if *instruction == Instruction::Left {
+                        -1
+                    } else {
+                        1
+                    });
+                    if state.pointer.is_some_and(|pointer| pointer < 0) {
+                        self.report(LintKind::NegativePointer, positions, None);
+                        // Only the first move off the tape is reported.
+                        block.state.pointer = None;
+                    }
+                }
+                Instruction::Input => state.set_current(None),
+                Instruction::Output => {}
+                Instruction::Loop(body) => {
+                    if state.current() == Some(0) {
+                        let cause = block.previous_loop.map(|loop_positions| {
+                            Span::from(loop_positions.to - 1, loop_positions.to)
+                        });
+                        self.report(LintKind::DeadLoop, positions, cause);
+                        block.previous_loop = Some(positions);
+                        block.position += size;
+                        continue;
+                    }
+                    if does_nothing(body) {
+                        self.report(LintKind::InfiniteLoop, positions, None);
+                        let rest = &block.instructions[block.next..];
+                        let end = rest.iter().fold(positions.to, |end, instruction| {
+                            end + self.size(instruction, end)
+                        });
+                        if nonzero(block.state.current()) && positions.to < end {
+                            self.report(
+                                LintKind::UnreachableCode,
+                                Span::from(positions.to, end),
+                                Some(positions),
+                            );
+                            blocks.pop();
+                            if let Some(outer) = blocks.last_mut() {
+                                self.after_loop(outer);
+                            }
+                            continue;
+                        }
+                    }
+
+                    self.cancelling_pairs(body, position + 1);
+                    blocks.push(Block {
+                        instructions: body,
+                        next: 0,
+                        position: position + 1,
+                        // The body may never run, so moves in it aren't reported.
+                        state: State::unknown(None),
+                        previous_loop: None,
+                    });
+                    continue;
+                }
+            }
+            block.previous_loop = None;
+            block.position += size;
+        }
+    }
+
+    /// Where the pointer is after the loop at `block.position`, if it is known.
+    fn loop_pointer(&self, block: &Block) -> Option<isize> {
+        let net_move = self.loops[&block.position].net_move;
+        block.state.pointer.filter(|_| net_move == Some(0))
+    }
+
+    /// Moves past the loop that was just checked in `block`.
+    fn after_loop(&self, block: &mut Block) {
+        let size = self.loops[&block.position].size;
+        block.state = State::unknown(self.loop_pointer(block));
+        block.state.set_current(Some(0));
+        block.previous_loop = Some(Span::from(block.position, block.position + size));
+        block.position += size;
+    }
+
+    fn cancelling_pairs(&mut self, instructions: &[Instruction], position: usize) {
+        let mut position = position;
+        let mut pairs = instructions.iter().peekable();
+        while let Some(instruction) = pairs.next() {
+            let cancels = pairs.peek().is_some_and(|next| cancels(instruction, next));
+            if cancels {
+                self.report(
+                    LintKind::CancellingPair,
+                    Span::from(position, position + 2),
+                    None,
+                );
+                pairs.next();
+                position += 2;
+            } else {
+                position += self.size(instruction, position);
+            }
+        }
+    }
+}
+
+/// Whether the value is nonzero for every cell width.
+fn nonzero(value: Option<i64>) -> bool {
+    value.is_some_and(|value| value % 256 != 0)
+}
+
+/// Whether a loop body leaves the pointer and every cell as it found them.
+fn does_nothing(body: &[Instruction]) -> bool {
+    let mut offset = 0isize;
+    let mut changes: HashMap<isize, i64> = HashMap::new();
+    for instruction in body {
+        match instruction {
+            Instruction::Add => *changes.entry(offset).or_default() += 1,
+            Instruction::Subtract => *changes.entry(offset).or_default() -= 1,
+            Instruction::Left => offset -= 1,
+            Instruction::Right => offset += 1,
+            _ => return false,
+        }
+    }
+    offset == 0 && changes.values().all(|&change| change == 0)
+}
+
+#[test]
+fn lints() {
+    use crate::Parser;
+    use lexer::lex;
+
+    let check = |source: &str, lints: Lints| {
+        let (instructions, spans) = Parser::new(lex(source)).parse_with_spans().unwrap();
+        lint(&instructions, &spans, lints)
+            .into_iter()
+            .map(|lint| (lint.kind, lint.span, lint.cause))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        check("[comment] +[->+<][-]", Lints::default()),
+        vec![
+            (LintKind::DeadLoop, Span::from(0, 9), None),
+            (
+                LintKind::DeadLoop,
+                Span::from(17, 20),
+                Some(Span::from(16, 17))
+            ),
+        ]
+    );
+    assert_eq!(
+        check("+>+-<<,[]>", Lints::default()),
+        vec![
+            (LintKind::CancellingPair, Span::from(2, 4), None),
+            (LintKind::NegativePointer, Span::from(5, 6), None),
+            (LintKind::InfiniteLoop, Span::from(7, 9), None),
+        ]
+    );
+    assert_eq!(
+        check("+[+-]>.", Lints::default().allow(LintKind::CancellingPair)),
+        vec![
+            (LintKind::InfiniteLoop, Span::from(1, 5), None),
+            (
+                LintKind::UnreachableCode,
+                Span::from(5, 7),
+                Some(Span::from(1, 5))
+            ),
+        ]
+    );
+    // The pointer is only known after loops that return to where they started.
+    assert_eq!(
+        check("+>+[<[-]>]<<", Lints::default()),
+        vec![(LintKind::NegativePointer, Span::from(11, 12), None)]
+    );
+    assert!(check("+>+[<]<<", Lints::default()).is_empty());
+    // Loops may never run, so moves in them aren't reported.
+    assert!(check(",[<]", Lints::default()).is_empty());
+    assert!(check(",[<+>-]", Lints::default()).is_empty());
+    // Moving the pointer doesn't copy the known cells.
+    assert!(check(&"+>".repeat(100_000), Lints::default()).is_empty());
+    let nested = format!(",{}-{}[]", "[".repeat(100_000), "]".repeat(100_000));
+    assert_eq!(
+        check(&nested, Lints::default()),
+        vec![(
+            LintKind::DeadLoop,
+            Span::from(200_002, 200_004),
+            Some(Span::from(200_001, 200_002))
+        )]
+    );
+    assert_eq!("dead-loop".parse(), Ok(LintKind::DeadLoop));
+}